@@ -6,21 +6,40 @@ pub mod window;
 mod math {
     pub mod cg;
     pub mod mat;
+    pub mod noise;
     pub mod quaternion;
     pub mod vec;
     pub mod angle;
 }
 
 pub mod logic {
+    mod biome;
+    mod block_registry;
+    mod brush;
     pub mod camera;
     mod chunk;
+    mod chunk_mesh_worker;
     mod chunk_mesher;
+    mod chunk_storage;
+    mod chunk_streamer;
+    mod density_generator;
+    mod history;
     mod chunk_render;
     pub mod controller;
     mod light;
+    mod mesh_export;
+    mod outline_render;
+    mod raycast;
+    mod region;
     mod render_controller;
     mod scene;
+    mod schematic;
+    mod structures;
+    #[cfg(test)]
+    mod test_utils;
     mod voxel;
+    mod vox;
+    mod world_generator;
     mod key_input;
 }
 
@@ -0,0 +1,371 @@
+use std::fmt;
+
+use crate::modules::math::noise::{Fractal, Noise};
+
+use super::{
+    block_registry::{Block, BlockRegistry},
+    world_generator::TerrainBlocks,
+};
+
+/// Biomes shipped with the engine, see the file for the format
+pub const DEFAULT_BIOMES: &str = include_str!("../../data/biomes.txt");
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    pub name: String,
+    /// Climate point of the biome, both in -1..=1
+    pub temperature: f32,
+    pub humidity: f32,
+    pub blocks: TerrainBlocks,
+    /// Multiplies the height differences of the terrain
+    pub height_scale: f32,
+    /// Chance of a decoration on every surface voxel
+    pub decoration_density: f32,
+}
+
+impl Biome {
+    /// Biome of a world without biomes
+    pub fn uniform(blocks: TerrainBlocks) -> Self {
+        Self {
+            name: "default".into(),
+            temperature: 0.0,
+            humidity: 0.0,
+            blocks,
+            height_scale: 1.0,
+            decoration_density: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BiomeParseError {
+    /// 1 based
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for BiomeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parses `[block ..]` and `[biome ..]` sections of `key = value` lines.
+/// Blocks are registered in `registry`, grass, dirt and stone always are
+pub fn parse_biomes(
+    source: &str,
+    registry: &mut BlockRegistry,
+) -> Result<Vec<Biome>, BiomeParseError> {
+    let defaults = TerrainBlocks::register(registry, 3);
+    let mut biomes = Vec::new();
+    let mut section: Option<Section> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |message: String| BiomeParseError {
+            line: line_number,
+            message,
+        };
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            if let Some(done) = section.take() {
+                biomes.extend(done.finish(registry)?);
+            }
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| error("missing `]`".into()))?;
+            section = Some(match header.split_whitespace().collect::<Vec<_>>()[..] {
+                ["block", name] => Section::Block {
+                    line: line_number,
+                    name: name.into(),
+                    color: None,
+                },
+                ["biome", name] => Section::Biome {
+                    line: line_number,
+                    biome: Biome {
+                        name: name.into(),
+                        ..Biome::uniform(defaults)
+                    },
+                    set: Vec::new(),
+                },
+                _ => return Err(error(format!("unknown section `{header}`"))),
+            });
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| error("expected `key = value`".into()))?;
+        let number = || {
+            value
+                .parse::<f32>()
+                .map_err(|_| error(format!("`{value}` is not a number")))
+        };
+        let block = |registry: &BlockRegistry| {
+            registry
+                .id(value)
+                .ok_or_else(|| error(format!("unknown block `{value}`")))
+        };
+        match &mut section {
+            None => return Err(error("expected a section first".into())),
+            Some(Section::Block { color, .. }) => match key {
+                "color" => {
+                    let channels: Vec<_> = value.split_whitespace().map(str::parse::<u8>).collect();
+                    match channels[..] {
+                        [Ok(r), Ok(g), Ok(b), Ok(a)] => *color = Some([r, g, b, a]),
+                        _ => return Err(error(format!("`{value}` is not `r g b a`"))),
+                    }
+                }
+                _ => return Err(error(format!("unknown block property `{key}`"))),
+            },
+            Some(Section::Biome { biome, set, .. }) => {
+                match key {
+                    "temperature" => biome.temperature = number()?,
+                    "humidity" => biome.humidity = number()?,
+                    "surface" => biome.blocks.surface = block(registry)?,
+                    "filler" => biome.blocks.filler = block(registry)?,
+                    "stone" => biome.blocks.stone = block(registry)?,
+                    "filler_depth" => {
+                        biome.blocks.filler_depth = value
+                            .parse()
+                            .map_err(|_| error(format!("`{value}` is not an integer")))?
+                    }
+                    "height_scale" => {
+                        // divides the density gradient, NaN fails too
+                        let scale = number()?;
+                        if scale.is_nan() || scale <= 0.0 {
+                            return Err(error(format!("height scale `{value}` is not positive")));
+                        }
+                        biome.height_scale = scale;
+                    }
+                    "decoration_density" => biome.decoration_density = number()?,
+                    _ => return Err(error(format!("unknown biome property `{key}`"))),
+                }
+                set.push(key.to_owned());
+            }
+        }
+    }
+    if let Some(done) = section.take() {
+        biomes.extend(done.finish(registry)?);
+    }
+    Ok(biomes)
+}
+
+enum Section {
+    Block {
+        line: usize,
+        name: String,
+        color: Option<[u8; 4]>,
+    },
+    Biome {
+        line: usize,
+        biome: Biome,
+        /// Keys given in the section
+        set: Vec<String>,
+    },
+}
+
+impl Section {
+    fn finish(self, registry: &mut BlockRegistry) -> Result<Option<Biome>, BiomeParseError> {
+        match self {
+            Section::Block { line, name, color } => {
+                let color = color.ok_or_else(|| BiomeParseError {
+                    line,
+                    message: format!("block `{name}` has no color"),
+                })?;
+                registry.register(Block::new(name, color));
+                Ok(None)
+            }
+            Section::Biome { line, biome, set } => {
+                for required in ["temperature", "humidity"] {
+                    if !set.iter().any(|key| key == required) {
+                        return Err(BiomeParseError {
+                            line,
+                            message: format!("biome `{}` has no {required}", biome.name),
+                        });
+                    }
+                }
+                Ok(Some(biome))
+            }
+        }
+    }
+}
+
+/// Biome and blended terrain shape of a column
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeSample<'a> {
+    /// Closest biome in climate space
+    pub biome: &'a Biome,
+    /// Weighted by climate distance, so it changes smoothly across borders
+    pub height_scale: f32,
+}
+
+/// Assigns biomes to columns from temperature and humidity noise
+pub struct BiomeMap {
+    biomes: Vec<Biome>,
+    temperature: Noise,
+    humidity: Noise,
+    climate: Fractal,
+    /// Climate distance over which neighbouring biomes are mixed
+    blend: f32,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u64, biomes: Vec<Biome>) -> Self {
+        assert!(!biomes.is_empty(), "BiomeMap needs at least one biome");
+        let noise = Noise::new(seed);
+        Self {
+            biomes,
+            // apart from the salts the terrain generators use
+            temperature: noise.derive(16),
+            humidity: noise.derive(17),
+            climate: Fractal {
+                octaves: 2,
+                frequency: 1.0 / 512.0,
+                ..Default::default()
+            },
+            blend: 0.15,
+        }
+    }
+
+    pub fn biomes(&self) -> &[Biome] {
+        &self.biomes
+    }
+
+    /// Temperature and humidity, both in -1..=1
+    pub fn climate(&self, [x, y]: [isize; 2]) -> [f32; 2] {
+        let pos = [x as f32, y as f32];
+        // fractal noise rarely leaves -0.5..=0.5
+        [&self.temperature, &self.humidity]
+            .map(|noise| (noise.fractal2(pos, self.climate) * 2.0).clamp(-1.0, 1.0))
+    }
+
+    pub fn sample(&self, column: [isize; 2]) -> BiomeSample<'_> {
+        self.sample_climate(self.climate(column))
+    }
+
+    pub fn sample_climate(&self, [temperature, humidity]: [f32; 2]) -> BiomeSample<'_> {
+        let distances: Vec<f32> = self
+            .biomes
+            .iter()
+            .map(|b| [b.temperature - temperature, b.humidity - humidity])
+            .map(|[t, h]| (t * t + h * h).sqrt())
+            .collect();
+        let (closest, &nearest) = distances
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+
+        let mut weights = 0.0;
+        let mut height_scale = 0.0;
+        for (biome, distance) in self.biomes.iter().zip(&distances) {
+            // relative to the closest biome, far ones drop to 0 quickly
+            let weight = (-(distance - nearest) / self.blend).exp();
+            weights += weight;
+            height_scale += biome.height_scale * weight;
+        }
+        BiomeSample {
+            biome: &self.biomes[closest],
+            height_scale: height_scale / weights,
+        }
+    }
+}
+
+#[cfg(test)]
+mod biome_tests {
+    use crate::modules::logic::block_registry::BlockRegistry;
+
+    use super::{parse_biomes, BiomeMap, DEFAULT_BIOMES};
+
+    const SOURCE: &str = "
+        [block sand] # comment
+        color = 1 2 3 255
+
+        [biome desert]
+        temperature = 0.5
+        humidity = -1
+        surface = sand
+        height_scale = 0.5
+
+        [biome hills]
+        temperature = -0.5
+        humidity = 1
+        height_scale = 2
+        decoration_density = 0.1
+    ";
+
+    #[test]
+    fn test_parse() {
+        let mut registry = BlockRegistry::default();
+        let biomes = parse_biomes(SOURCE, &mut registry).unwrap();
+        let sand = registry.id("sand").unwrap();
+        assert_eq!([1, 2, 3, 255], registry[sand].color);
+
+        assert_eq!(2, biomes.len());
+        assert_eq!("desert", biomes[0].name);
+        assert_eq!(sand, biomes[0].blocks.surface);
+        assert_eq!(registry.id("dirt"), Some(biomes[0].blocks.filler));
+        assert_eq!([0.5, -1.0], [biomes[0].temperature, biomes[0].humidity]);
+        assert_eq!(0.0, biomes[0].decoration_density);
+        assert_eq!(registry.id("grass"), Some(biomes[1].blocks.surface));
+        assert_eq!(0.1, biomes[1].decoration_density);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| {
+            let error = parse_biomes(source, &mut BlockRegistry::default()).unwrap_err();
+            (error.line, error.message)
+        };
+        assert_eq!(1, error("color = 1 2 3 4").0);
+        assert_eq!(2, error("[biome a]\ntemperature = hot").0);
+        assert_eq!(
+            (3, "unknown block `lava`".into()),
+            error("[biome a]\ntemperature = 0\nsurface = lava")
+        );
+        assert_eq!(1, error("[biome a]\ntemperature = 0\n").0);
+        assert_eq!(2, error("[block a]\ncolor = 1 2 3").0);
+        assert_eq!(1, error("[tree oak]").0);
+        assert_eq!(2, error("[biome a]\nwetness = 1").0);
+        assert_eq!(
+            (2, "height scale `0` is not positive".into()),
+            error("[biome a]\nheight_scale = 0")
+        );
+        assert_eq!(3, error("[biome a]\n\nheight_scale = -1.5").0);
+    }
+
+    #[test]
+    fn test_default_biomes() {
+        let mut registry = BlockRegistry::default();
+        let biomes = parse_biomes(DEFAULT_BIOMES, &mut registry).unwrap();
+        assert!(biomes.len() >= 3);
+        assert!(registry.id("sand").is_some());
+    }
+
+    #[test]
+    fn test_sample() {
+        let biomes = parse_biomes(SOURCE, &mut BlockRegistry::default()).unwrap();
+        let map = BiomeMap::new(0, biomes);
+        assert_eq!("desert", map.sample_climate([0.6, -0.9]).biome.name);
+        assert_eq!("hills", map.sample_climate([-0.1, 0.3]).biome.name);
+        assert!((map.sample_climate([0.5, -1.0]).height_scale - 0.5).abs() < 1e-3);
+
+        // heights blend on the border between both biomes
+        let mut previous = map.sample_climate([1.0, -1.0]).height_scale;
+        for i in 1..=1000 {
+            let t = i as f32 / 1000.0;
+            let scale = map
+                .sample_climate([1.0 - 2.0 * t, -1.0 + 2.0 * t])
+                .height_scale;
+            assert!((scale - previous).abs() < 0.1);
+            previous = scale;
+        }
+        assert!((previous - 2.0).abs() < 1e-3);
+    }
+}
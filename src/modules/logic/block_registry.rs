@@ -0,0 +1,127 @@
+use std::{collections::HashMap, ops::Index};
+
+use super::voxel::{Color, Voxel, AIR};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub name: String,
+    pub color: Color,
+    /// Hides faces of neighbouring voxels and casts ambient occlusion
+    pub opaque: bool,
+    /// Light level given off by the block, 0 for none
+    pub emissive: u8,
+    /// Blocks movement and stops rays
+    pub solid: bool,
+}
+
+impl Block {
+    /// Solid block, opaque unless the color is translucent
+    pub fn new(name: impl Into<String>, color: Color) -> Self {
+        Self {
+            name: name.into(),
+            color,
+            opaque: color[3] == u8::MAX,
+            emissive: 0,
+            solid: true,
+        }
+    }
+}
+
+/// Maps voxel ids to block types, id `AIR` is always registered
+#[derive(Clone)]
+pub struct BlockRegistry {
+    blocks: Vec<Block>,
+    ids: HashMap<String, Voxel>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let air = Block {
+            opaque: false,
+            solid: false,
+            ..Block::new("air", [0, 0, 0, 0])
+        };
+        Self {
+            ids: HashMap::from([(air.name.clone(), AIR)]),
+            blocks: vec![air],
+        }
+    }
+}
+
+impl BlockRegistry {
+    /// Returns the id already given to a block with the same name, if any
+    pub fn register(&mut self, block: Block) -> Voxel {
+        if let Some(&id) = self.ids.get(&block.name) {
+            return id;
+        }
+        let id = Voxel::try_from(self.blocks.len()).expect("Block registry is full");
+        self.ids.insert(block.name.clone(), id);
+        self.blocks.push(block);
+        id
+    }
+
+    /// Plain block named after its color
+    pub fn register_color(&mut self, color: Color) -> Voxel {
+        let [r, g, b, a] = color;
+        self.register(Block::new(format!("#{r:02x}{g:02x}{b:02x}{a:02x}"), color))
+    }
+
+    pub fn get(&self, id: Voxel) -> Option<&Block> {
+        self.blocks.get(id as usize)
+    }
+
+    pub fn id(&self, name: &str) -> Option<Voxel> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+}
+
+impl Index<Voxel> for BlockRegistry {
+    type Output = Block;
+
+    fn index(&self, id: Voxel) -> &Block {
+        self.get(id).expect("Unknown block id")
+    }
+}
+
+#[cfg(test)]
+mod block_registry_tests {
+    use crate::modules::logic::voxel::AIR;
+
+    use super::{Block, BlockRegistry};
+
+    #[test]
+    fn test_air() {
+        let registry = BlockRegistry::default();
+        assert_eq!(Some(AIR), registry.id("air"));
+        assert!(!registry[AIR].opaque);
+        assert!(!registry[AIR].solid);
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = BlockRegistry::default();
+        let stone = registry.register(Block::new("stone", [128, 128, 128, 255]));
+        let glass = registry.register(Block::new("glass", [200, 220, 255, 100]));
+        assert_eq!(
+            stone,
+            registry.register(Block::new("stone", [0, 0, 0, 255]))
+        );
+        assert_eq!([128, 128, 128, 255], registry[stone].color);
+        assert!(registry[stone].opaque);
+        assert!(!registry[glass].opaque);
+        assert_eq!(3, registry.len());
+    }
+
+    #[test]
+    fn test_register_color() {
+        let mut registry = BlockRegistry::default();
+        let red = registry.register_color([255, 0, 0, 255]);
+        assert_eq!(red, registry.register_color([255, 0, 0, 255]));
+        assert_ne!(red, registry.register_color([255, 0, 0, 254]));
+        assert_eq!(Some(red), registry.id("#ff0000ff"));
+    }
+}
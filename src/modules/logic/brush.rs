@@ -0,0 +1,298 @@
+use std::collections::{HashSet, VecDeque};
+
+use super::{
+    scene::{ChunkIndex, Scene, WorldPos},
+    voxel::{Voxel, AIR},
+};
+
+/// Voxels a brush edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    /// Inclusive corners in any order
+    Box {
+        a: WorldPos,
+        b: WorldPos,
+    },
+    Sphere {
+        center: WorldPos,
+        radius: isize,
+    },
+    /// Upright, `base` is the center of the lowest layer
+    Cylinder {
+        base: WorldPos,
+        radius: isize,
+        height: isize,
+    },
+    /// Both ends included
+    Line {
+        from: WorldPos,
+        to: WorldPos,
+    },
+}
+
+impl Shape {
+    pub fn positions(&self) -> Vec<WorldPos> {
+        match *self {
+            Shape::Box { a, b } => {
+                let min = [0, 1, 2].map(|i| a[i].min(b[i]));
+                let max = [0, 1, 2].map(|i| a[i].max(b[i]));
+                let mut positions = Vec::new();
+                for z in min[2]..=max[2] {
+                    for y in min[1]..=max[1] {
+                        for x in min[0]..=max[0] {
+                            positions.push([x, y, z]);
+                        }
+                    }
+                }
+                positions
+            }
+            Shape::Sphere { center, radius } => Shape::Box {
+                a: center.map(|c| c - radius),
+                b: center.map(|c| c + radius),
+            }
+            .positions()
+            .into_iter()
+            .filter(|pos| {
+                let d = [0, 1, 2].map(|i| pos[i] - center[i]);
+                d[0] * d[0] + d[1] * d[1] + d[2] * d[2] <= radius * radius
+            })
+            .collect(),
+            Shape::Cylinder {
+                base,
+                radius,
+                height,
+            } => Shape::Box {
+                a: [base[0] - radius, base[1] - radius, base[2]],
+                b: [base[0] + radius, base[1] + radius, base[2] + height - 1],
+            }
+            .positions()
+            .into_iter()
+            .filter(|pos| {
+                let [dx, dy] = [pos[0] - base[0], pos[1] - base[1]];
+                dx * dx + dy * dy <= radius * radius
+            })
+            .collect(),
+            Shape::Line { from, to } => line(from, to),
+        }
+    }
+}
+
+/// 3D Bresenham, every step moves by at most one voxel on each axis
+fn line(from: WorldPos, to: WorldPos) -> Vec<WorldPos> {
+    let delta = [0, 1, 2].map(|i| (to[i] - from[i]).abs());
+    let step = [0, 1, 2].map(|i| (to[i] - from[i]).signum());
+    // the axis with the largest delta advances every step
+    let main = (0..3).max_by_key(|&i| delta[i]).unwrap();
+    let mut errors = [0, 1, 2].map(|i| 2 * delta[i] - delta[main]);
+
+    let mut pos = from;
+    let mut positions = vec![pos];
+    for _ in 0..delta[main] {
+        pos[main] += step[main];
+        for i in (0..3).filter(|&i| i != main) {
+            if errors[i] > 0 {
+                pos[i] += step[i];
+                errors[i] -= 2 * delta[main];
+            }
+            errors[i] += 2 * delta[i];
+        }
+        positions.push(pos);
+    }
+    positions
+}
+
+/// Only writes voxels that change, so untouched chunks stay clean
+fn set(scene: &mut Scene, pos: WorldPos, voxel: Voxel, touched: &mut HashSet<ChunkIndex>) {
+    if scene.get_voxel(pos) != voxel {
+        scene.set_voxel(pos, voxel);
+        touched.insert(Scene::locate(pos).0);
+    }
+}
+
+/// Returns the chunks with changed voxels
+pub fn fill(scene: &mut Scene, shape: &Shape, voxel: Voxel) -> HashSet<ChunkIndex> {
+    let mut touched = HashSet::new();
+    for pos in shape.positions() {
+        set(scene, pos, voxel, &mut touched);
+    }
+    touched
+}
+
+pub fn clear(scene: &mut Scene, shape: &Shape) -> HashSet<ChunkIndex> {
+    fill(scene, shape, AIR)
+}
+
+/// Turns the `from` voxels in the shape into `to`
+pub fn replace(scene: &mut Scene, shape: &Shape, from: Voxel, to: Voxel) -> HashSet<ChunkIndex> {
+    let mut touched = HashSet::new();
+    for pos in shape.positions() {
+        if scene.get_voxel(pos) == from {
+            set(scene, pos, to, &mut touched);
+        }
+    }
+    touched
+}
+
+/// Replaces the voxels connected to `start` through faces that equal it.
+/// Never leaves the inclusive box `bounds`, air would otherwise run on forever
+pub fn flood_fill(
+    scene: &mut Scene,
+    start: WorldPos,
+    voxel: Voxel,
+    bounds: [WorldPos; 2],
+) -> HashSet<ChunkIndex> {
+    let mut touched = HashSet::new();
+    let target = scene.get_voxel(start);
+    let inside = |pos: WorldPos| (0..3).all(|i| (bounds[0][i]..=bounds[1][i]).contains(&pos[i]));
+    if target == voxel || !inside(start) {
+        return touched;
+    }
+
+    let mut queue = VecDeque::from([start]);
+    set(scene, start, voxel, &mut touched);
+    while let Some(pos) = queue.pop_front() {
+        for axis in 0..3 {
+            for step in [-1, 1] {
+                let mut next = pos;
+                next[axis] += step;
+                // filled voxels no longer match, so none is visited twice
+                if inside(next) && scene.get_voxel(next) == target {
+                    set(scene, next, voxel, &mut touched);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    touched
+}
+
+#[cfg(test)]
+mod brush_tests {
+    use std::collections::HashSet;
+
+    use crate::modules::logic::{
+        block_registry::BlockRegistry,
+        scene::Scene,
+        voxel::{Voxel, AIR},
+    };
+
+    use super::{clear, fill, flood_fill, replace, Shape};
+
+    const STONE: Voxel = 1;
+    const GLASS: Voxel = 2;
+
+    fn scene() -> Scene {
+        let mut registry = BlockRegistry::default();
+        registry.register_color([128, 128, 128, 255]);
+        registry.register_color([200, 200, 255, 100]);
+        Scene::new(registry)
+    }
+
+    #[test]
+    fn test_box() {
+        let mut scene = scene();
+        let shape = Shape::Box {
+            a: [33, 2, 0],
+            b: [30, 0, 1],
+        };
+        let touched = fill(&mut scene, &shape, STONE);
+        assert_eq!(HashSet::from([[0, 0, 0], [1, 0, 0]]), touched);
+        assert_eq!(4 * 3 * 2, shape.positions().len());
+        assert_eq!(STONE, scene.get_voxel([30, 2, 1]));
+        assert_eq!(AIR, scene.get_voxel([29, 2, 1]));
+
+        // nothing changes the second time
+        assert!(fill(&mut scene, &shape, STONE).is_empty());
+        let inner = Shape::Box {
+            a: [32, 0, 0],
+            b: [33, 2, 1],
+        };
+        assert_eq!(HashSet::from([[1, 0, 0]]), clear(&mut scene, &inner));
+        assert_eq!(STONE, scene.get_voxel([31, 0, 0]));
+    }
+
+    #[test]
+    fn test_round_shapes() {
+        let sphere = Shape::Sphere {
+            center: [-1, 0, 0],
+            radius: 2,
+        };
+        let positions = sphere.positions();
+        // 1 + 6 at distance 1, 12 at sqrt 2, 8 at sqrt 3, 6 at 2
+        assert_eq!(33, positions.len());
+        assert!(positions.contains(&[-3, 0, 0]) && !positions.contains(&[-3, 1, 0]));
+
+        let mut scene = scene();
+        let touched = fill(&mut scene, &sphere, STONE);
+        // reaches into the chunks below zero on every axis
+        assert_eq!(8, touched.len());
+
+        let cylinder = Shape::Cylinder {
+            base: [0, 0, 5],
+            radius: 1,
+            height: 3,
+        };
+        let positions = cylinder.positions();
+        assert_eq!(5 * 3, positions.len());
+        assert!(positions.iter().all(|pos| (5..8).contains(&pos[2])));
+    }
+
+    #[test]
+    fn test_line() {
+        for (from, to) in [
+            ([0, 0, 0], [7, 3, -2]),
+            ([5, -4, 2], [-6, 9, 2]),
+            ([1, 1, 1], [1, 1, 1]),
+            ([0, 0, 0], [0, 0, -40]),
+        ] {
+            let positions = Shape::Line { from, to }.positions();
+            let longest = (0..3).map(|i| (to[i] - from[i]).abs()).max().unwrap();
+            assert_eq!(longest as usize + 1, positions.len());
+            assert_eq!(Some(&from), positions.first());
+            assert_eq!(Some(&to), positions.last());
+            for pair in positions.windows(2) {
+                assert!((0..3).all(|i| (pair[1][i] - pair[0][i]).abs() <= 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let mut scene = scene();
+        // a closed 5x5x5 glass box with a hollow 3x3x3 inside
+        let walls = Shape::Box {
+            a: [-2, -2, -2],
+            b: [2, 2, 2],
+        };
+        fill(&mut scene, &walls, GLASS);
+        let inside = Shape::Box {
+            a: [-1, -1, -1],
+            b: [1, 1, 1],
+        };
+        clear(&mut scene, &inside);
+
+        let bounds = [[-10, -10, -10], [10, 10, 10]];
+        flood_fill(&mut scene, [0, 0, 0], STONE, bounds);
+        assert_eq!(STONE, scene.get_voxel([1, 1, 1]));
+        assert_eq!(GLASS, scene.get_voxel([2, 2, 2]));
+        assert_eq!(AIR, scene.get_voxel([3, 0, 0]));
+
+        // air above the box only fills up to the bounds, z 2 to 6 and x, y -3 to 3,
+        // including a hole in the lid
+        scene.set_voxel([0, 0, 2], AIR);
+        let touched = flood_fill(&mut scene, [0, 0, 5], GLASS, [[-3, -3, 2], [3, 3, 6]]);
+        assert_eq!(GLASS, scene.get_voxel([3, 3, 6]));
+        assert_eq!(GLASS, scene.get_voxel([0, 0, 2]));
+        assert_eq!(AIR, scene.get_voxel([0, 0, 7]));
+        assert_eq!(AIR, scene.get_voxel([3, 3, 1]));
+        assert_eq!(
+            HashSet::from([[0, 0, 0], [-1, 0, 0], [0, -1, 0], [-1, -1, 0]]),
+            touched
+        );
+
+        let touched = replace(&mut scene, &walls, STONE, AIR);
+        assert_eq!(8, touched.len());
+        assert_eq!(AIR, scene.get_voxel([1, 1, 1]));
+        assert_eq!(GLASS, scene.get_voxel([2, 2, 2]));
+    }
+}
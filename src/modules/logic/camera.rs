@@ -47,6 +47,11 @@ impl OrientedCamera {
         self.orientation =
             Quaternion::from([Angle::from_deg(delta), 0.0.into(), 0.0.into()]) * self.orientation;
     }
+    /// Direction the camera looks in, local y
+    pub fn forward(&self) -> Vec3 {
+        let vec = self.rotation_matrix().mult([0.0, 1.0, 0.0, 0.0]);
+        [vec[0], vec[1], vec[2]]
+    }
 }
 
 pub struct TrackingCamera {
@@ -1,57 +1,99 @@
-use crate::{for_multi, modules::logic::voxel::Color};
+use crate::for_multi;
 
-use super::voxel::Voxel;
+use super::{
+    block_registry::BlockRegistry,
+    chunk_storage::ChunkStorage,
+    voxel::{Voxel, AIR},
+};
 
+#[derive(Clone)]
 pub struct Chunk {
-    pub voxels: [[[Voxel; Self::DIMENSIONS]; Self::DIMENSIONS]; Self::DIMENSIONS],
+    storage: ChunkStorage,
 }
 
 impl Chunk {
     pub const DIMENSIONS: usize = 32;
+
+    /// `pos` is x, y, z inside the chunk
+    pub fn get(&self, pos: [usize; 3]) -> Voxel {
+        self.storage.get(Self::storage_index(pos))
+    }
+
+    pub fn set(&mut self, pos: [usize; 3], voxel: Voxel) {
+        self.storage.set(Self::storage_index(pos), voxel);
+    }
+
+    /// Releases memory of block types no longer present
+    pub fn compact(&mut self) {
+        self.storage.compact();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storage == ChunkStorage::Uniform(AIR)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.storage.memory_usage()
+    }
+
+    fn storage_index([x, y, z]: [usize; 3]) -> usize {
+        const D: usize = Chunk::DIMENSIONS;
+        // a wrapped index would silently hit another voxel
+        assert!(
+            x < D && y < D && z < D,
+            "{:?} is outside of the chunk",
+            [x, y, z]
+        );
+        (z * D + y) * D + x
+    }
 }
 
 impl Chunk {
     pub fn empty() -> Self {
-        const D: usize = Chunk::DIMENSIONS;
+        Self::filled(AIR)
+    }
+
+    pub fn filled(voxel: Voxel) -> Self {
         Self {
-            voxels: [[[Voxel::None; D]; D]; D],
+            storage: ChunkStorage::Uniform(voxel),
         }
     }
 
-    pub fn random() -> Self {
+    pub fn random(registry: &mut BlockRegistry) -> Self {
         const D: usize = Chunk::DIMENSIONS;
         let mut random = Self::empty();
         for_multi!(0..D, 0..D, 0..D; |x: usize, y: usize, z: usize| {
             let seed = 8*x + 2*y + 3*z;
             let condition = seed % 27 == 0;
-            random.voxels[z][y][x] = if condition {
+            let voxel = if condition {
                 let color = [
                     (x * 23 % 255) as u8,
                     (y * 224 % 255) as u8,
                     (z * 25 % 255) as u8,
                     100
                 ];
-                Some(color)
+                registry.register_color(color)
             }
             else {
-                None
-            }
+                AIR
+            };
+            random.set([x, y, z], voxel);
         });
         random
     }
 
-    pub fn cat() -> Self {
+    pub fn cat(registry: &mut BlockRegistry) -> Self {
         const D: usize = Chunk::DIMENSIONS;
         let mut cat = Self::empty();
 
-        let w = Some([255, 255, 255, 255]);
-        let g = Some([204, 207, 221, 255]);
-        let d = Some([67, 74, 103, 255]);
-        let b = Some([20, 20, 20, 255]);
-        let c = Some([3, 157, 227, 255]);
-        let o = Some([234, 117, 17, 255]);
-        let p = Some([235, 128, 193, 255]);
-        const N: Option<Color> = None;
+        let w = registry.register_color([255, 255, 255, 255]);
+        let g = registry.register_color([204, 207, 221, 255]);
+        let d = registry.register_color([67, 74, 103, 255]);
+        let b = registry.register_color([20, 20, 20, 255]);
+        let c = registry.register_color([3, 157, 227, 255]);
+        let o = registry.register_color([234, 117, 17, 255]);
+        let p = registry.register_color([235, 128, 193, 255]);
+        const N: Voxel = AIR;
 
         let pixel_art: [[Voxel; 18]; 17] = [
             [N, b, b, b, N, N, N, N, N, b, b, b, N, N, N, N, N, N],
@@ -75,9 +117,52 @@ impl Chunk {
 
         for y in 0..pixel_art.len() {
             for x in 0..pixel_art[y].len() {
-                cat.voxels[D - y - 10][D / 2][10 + x] = pixel_art[y][x];
+                cat.set([10 + x, D / 2, D - y - 10], pixel_art[y][x]);
             }
         }
         cat
     }
 }
+
+#[cfg(test)]
+mod chunk_tests {
+    use crate::modules::logic::{
+        block_registry::BlockRegistry,
+        voxel::{Voxel, AIR},
+    };
+
+    use super::Chunk;
+
+    const D: usize = Chunk::DIMENSIONS;
+    const DENSE: usize = D * D * D * size_of::<Voxel>();
+
+    #[test]
+    fn test_get_set() {
+        let mut chunk = Chunk::empty();
+        chunk.set([1, 2, 3], 5);
+        chunk.set([3, 2, 1], 6);
+        assert_eq!(5, chunk.get([1, 2, 3]));
+        assert_eq!(6, chunk.get([3, 2, 1]));
+        assert_eq!(AIR, chunk.get([2, 2, 2]));
+
+        chunk.set([1, 2, 3], AIR);
+        chunk.set([3, 2, 1], AIR);
+        chunk.compact();
+        assert!(chunk.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_bounds() {
+        Chunk::empty().set([D, 0, 0], 1);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut registry = BlockRegistry::default();
+        assert!(Chunk::empty().memory_usage() * 1000 < DENSE);
+        assert!(Chunk::filled(1).memory_usage() * 1000 < DENSE);
+        // 8 block types fit into 4 bits
+        assert!(Chunk::cat(&mut registry).memory_usage() * 3 < DENSE);
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use super::{
+    block_registry::BlockRegistry,
+    chunk::Chunk,
+    chunk_mesher::{self, ChunkMesh, ChunkNeighbours, MeshSettings},
+    scene::{ChunkIndex, Scene},
+};
+
+/// Everything a worker needs to mesh a chunk without touching the scene
+pub struct MeshJob {
+    pub idx: ChunkIndex,
+    pub chunk: Chunk,
+    /// In `ChunkNeighbours` order
+    pub neighbours: [Option<Chunk>; 6],
+    pub registry: Arc<BlockRegistry>,
+    /// Lower is meshed first
+    pub priority: f32,
+    generation: u64,
+}
+
+impl MeshJob {
+    pub fn snapshot(scene: &Scene, idx: ChunkIndex, priority: f32) -> Option<Self> {
+        Some(Self {
+            idx,
+            chunk: scene.get_chunk(idx)?.clone(),
+            neighbours: scene.neighbours(idx).cloned(),
+            registry: scene.shared_registry(),
+            priority,
+            generation: 0,
+        })
+    }
+
+    fn mesh(&self, settings: MeshSettings) -> ChunkMesh {
+        let neighbours = ChunkNeighbours::from_owned(&self.neighbours);
+        chunk_mesher::mesh_with_neighbours(&self.chunk, &neighbours, &self.registry, settings)
+    }
+}
+
+impl PartialEq for MeshJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MeshJob {}
+
+impl PartialOrd for MeshJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MeshJob {
+    /// `BinaryHeap` pops the maximum, so the closest job has to be the greatest
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.total_cmp(&self.priority)
+    }
+}
+
+pub struct MeshResult {
+    pub idx: ChunkIndex,
+    pub mesh: ChunkMesh,
+    generation: u64,
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<MeshJob>,
+    /// Generation of the newest job of every chunk that is queued or being meshed
+    latest: HashMap<ChunkIndex, u64>,
+    next_generation: u64,
+    closed: bool,
+}
+
+impl Queue {
+    fn is_current(&self, idx: ChunkIndex, generation: u64) -> bool {
+        self.latest.get(&idx) == Some(&generation)
+    }
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+/// Meshes chunks on background threads, closest chunks first
+pub struct ChunkMeshWorkers {
+    shared: Arc<Shared>,
+    capacity: usize,
+    results: Receiver<MeshResult>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ChunkMeshWorkers {
+    pub fn new(threads: usize, capacity: usize, settings: MeshSettings) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::default()),
+            available: Condvar::new(),
+        });
+        let (sender, results) = mpsc::channel();
+        let threads = (0..threads)
+            .map(|_| {
+                let shared = shared.clone();
+                let sender = sender.clone();
+                thread::spawn(move || work(&shared, &sender, settings))
+            })
+            .collect();
+        Self {
+            shared,
+            capacity,
+            results,
+            threads,
+        }
+    }
+
+    /// Hands the job back if the queue is full.
+    /// Older jobs of the same chunk are cancelled
+    pub fn submit(&self, mut job: MeshJob) -> Result<(), MeshJob> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        let replaced = queue.latest.contains_key(&job.idx);
+        if replaced {
+            queue.jobs.retain(|queued| queued.idx != job.idx);
+        }
+        if queue.jobs.len() >= self.capacity {
+            if replaced {
+                // the old job is gone, its result is stale either way
+                queue.latest.remove(&job.idx);
+            }
+            return Err(job);
+        }
+        job.generation = queue.next_generation;
+        queue.next_generation += 1;
+        queue.latest.insert(job.idx, job.generation);
+        queue.jobs.push(job);
+        self.shared.available.notify_one();
+        Ok(())
+    }
+
+    /// Drops the pending job of the chunk, a running one is discarded when it finishes
+    pub fn cancel(&self, idx: ChunkIndex) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.latest.remove(&idx).is_some() {
+            queue.jobs.retain(|queued| queued.idx != idx);
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.shared.queue.lock().unwrap().jobs.len() >= self.capacity
+    }
+
+    /// Finished meshes of the latest submitted snapshots
+    pub fn poll(&self) -> Vec<(ChunkIndex, ChunkMesh)> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        self.results
+            .try_iter()
+            .filter(|result| {
+                let current = queue.is_current(result.idx, result.generation);
+                if current {
+                    queue.latest.remove(&result.idx);
+                }
+                current
+            })
+            .map(|result| (result.idx, result.mesh))
+            .collect()
+    }
+}
+
+impl Drop for ChunkMeshWorkers {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.available.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn work(shared: &Shared, sender: &Sender<MeshResult>, settings: MeshSettings) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.closed {
+                    return;
+                }
+                if let Some(job) = queue.jobs.pop() {
+                    break job;
+                }
+                queue = shared.available.wait(queue).unwrap();
+            }
+        };
+        let Some(result) = finish(shared, job, settings) else {
+            continue;
+        };
+        if sender.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+/// `None` if a newer job of the chunk was submitted while meshing
+fn finish(shared: &Shared, job: MeshJob, settings: MeshSettings) -> Option<MeshResult> {
+    let mesh = job.mesh(settings);
+    if !shared
+        .queue
+        .lock()
+        .unwrap()
+        .is_current(job.idx, job.generation)
+    {
+        return None;
+    }
+    Some(MeshResult {
+        idx: job.idx,
+        mesh,
+        generation: job.generation,
+    })
+}
+
+#[cfg(test)]
+mod chunk_mesh_worker_tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use crate::modules::logic::{
+        block_registry::BlockRegistry,
+        chunk_mesher::{self, ChunkMesh, MeshSettings},
+        scene::Scene,
+    };
+
+    use super::{finish, ChunkMeshWorkers, MeshJob};
+
+    fn wait(workers: &ChunkMeshWorkers, count: usize) -> Vec<([isize; 3], ChunkMesh)> {
+        let mut results = Vec::new();
+        for _ in 0..200 {
+            results.extend(workers.poll());
+            if results.len() >= count {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        results
+    }
+
+    fn solid_scene() -> Scene {
+        let mut registry = BlockRegistry::default();
+        let solid = registry.register_color([255; 4]);
+        let mut scene = Scene::new(registry);
+        scene.set_voxel([0, 0, 0], solid);
+        scene.set_voxel([40, 0, 0], solid);
+        scene
+    }
+
+    #[test]
+    fn test_mesh_matches_sync() {
+        let scene = solid_scene();
+        let workers = ChunkMeshWorkers::new(2, 8, MeshSettings::default());
+        for idx in [[0, 0, 0], [1, 0, 0]] {
+            assert!(workers
+                .submit(MeshJob::snapshot(&scene, idx, 0.0).unwrap())
+                .is_ok());
+        }
+        let results = wait(&workers, 2);
+        assert_eq!(2, results.len());
+        for (idx, mesh) in results {
+            let chunk = scene.get_chunk(idx).unwrap();
+            let expected = chunk_mesher::mesh_with_neighbours(
+                chunk,
+                &scene.neighbours(idx),
+                scene.registry(),
+                MeshSettings::default(),
+            );
+            assert_eq!(expected.vertices, mesh.vertices);
+        }
+    }
+
+    #[test]
+    fn test_missing_chunk() {
+        assert!(MeshJob::snapshot(&solid_scene(), [3, 0, 0], 0.0).is_none());
+    }
+
+    #[test]
+    fn test_bounded_priority() {
+        let scene = solid_scene();
+        let workers = ChunkMeshWorkers::new(0, 2, MeshSettings::default());
+        let job = |priority| MeshJob::snapshot(&scene, [0, 0, 0], priority).unwrap();
+        let far = MeshJob {
+            idx: [1, 0, 0],
+            ..job(9.0)
+        };
+        assert!(workers.submit(far).is_ok());
+        assert!(workers.submit(job(1.0)).is_ok());
+        assert!(workers.is_full());
+        let rejected = MeshJob {
+            idx: [2, 0, 0],
+            ..job(0.0)
+        };
+        assert!(workers.submit(rejected).is_err());
+
+        let mut queue = workers.shared.queue.lock().unwrap();
+        assert_eq!([0, 0, 0], queue.jobs.pop().unwrap().idx);
+        assert_eq!([1, 0, 0], queue.jobs.pop().unwrap().idx);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let scene = solid_scene();
+        let workers = ChunkMeshWorkers::new(0, 4, MeshSettings::default());
+        let job = || MeshJob::snapshot(&scene, [0, 0, 0], 0.0).unwrap();
+        assert!(workers.submit(job()).is_ok());
+        assert!(workers.submit(job()).is_ok());
+        // resubmitting replaces the queued job
+        assert_eq!(1, workers.shared.queue.lock().unwrap().jobs.len());
+        workers.cancel([0, 0, 0]);
+        assert!(workers.shared.queue.lock().unwrap().jobs.is_empty());
+    }
+
+    #[test]
+    fn test_stale_results() {
+        let scene = solid_scene();
+        let settings = MeshSettings::default();
+        let job = || MeshJob::snapshot(&scene, [0, 0, 0], 0.0).unwrap();
+        // jobs are taken and finished here the way a worker thread would
+        let mut workers = ChunkMeshWorkers::new(0, 4, settings);
+        let (sender, results) = mpsc::channel();
+        workers.results = results;
+        let take =
+            |workers: &ChunkMeshWorkers| workers.shared.queue.lock().unwrap().jobs.pop().unwrap();
+
+        // a newer snapshot is submitted while the old one is meshed
+        assert!(workers.submit(job()).is_ok());
+        let running = take(&workers);
+        assert!(workers.submit(job()).is_ok());
+        assert!(finish(&workers.shared, running, settings).is_none());
+        let result = finish(&workers.shared, take(&workers), settings).unwrap();
+        sender.send(result).unwrap();
+        assert_eq!(1, workers.poll().len());
+
+        // cancelled after meshing, before the result is polled
+        assert!(workers.submit(job()).is_ok());
+        let result = finish(&workers.shared, take(&workers), settings).unwrap();
+        sender.send(result).unwrap();
+        workers.cancel([0, 0, 0]);
+        assert!(workers.poll().is_empty());
+    }
+}
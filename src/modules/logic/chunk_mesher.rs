@@ -1,88 +1,751 @@
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
 
-use crate::{
-    for_multi,
-    modules::{
-        logic::chunk::Chunk,
-        math::vec::{Vec3, VecAdd},
-    },
-};
+use crate::{for_multi, modules::logic::chunk::Chunk};
 
-use super::voxel::Color;
+use super::{
+    block_registry::BlockRegistry,
+    voxel::{Voxel, AIR},
+};
 
-#[derive(BufferContents, Vertex)]
+/// Packed vertex: 6 bits per local coordinate (0..=32), 3 bits of face id
+/// and 2 bits of ambient occlusion, decoded in `voxel_vertex.vert`
+#[derive(BufferContents, Vertex, Debug, Clone, Copy, PartialEq)]
 #[repr(C)]
 pub struct ChunkMeshVertex {
-    #[format(R32G32B32_SFLOAT)]
-    pub pos: [f32; 3],
-    #[format(R8G8B8A8_UNORM)]
-    pub color: [u8; 4],
+    #[format(R32_UINT)]
+    pub data: u32,
+    /// Looked up in the block colors of the registry by the shader
+    #[format(R32_UINT)]
+    pub block: u32,
+}
+
+impl ChunkMeshVertex {
+    const POS_BITS: u32 = 6;
+    const POS_MASK: u32 = (1 << Self::POS_BITS) - 1;
+    const FACE_SHIFT: u32 = 3 * Self::POS_BITS;
+    const FACE_MASK: u32 = 0b111;
+    const AO_SHIFT: u32 = Self::FACE_SHIFT + 3;
+    const AO_MASK: u32 = 0b11;
+
+    fn new(pos: [usize; 3], face: usize, ao: u8, block: Voxel) -> Self {
+        let [x, y, z] = pos.map(|c| c as u32 & Self::POS_MASK);
+        Self {
+            data: x
+                | y << Self::POS_BITS
+                | z << 2 * Self::POS_BITS
+                | (face as u32 & Self::FACE_MASK) << Self::FACE_SHIFT
+                | (ao as u32 & Self::AO_MASK) << Self::AO_SHIFT,
+            block: block as u32,
+        }
+    }
+
+    pub fn pos(&self) -> [u32; 3] {
+        [0, 1, 2].map(|i| self.data >> i * Self::POS_BITS & Self::POS_MASK)
+    }
+
+    pub fn face(&self) -> usize {
+        (self.data >> Self::FACE_SHIFT & Self::FACE_MASK) as usize
+    }
+
+    pub fn normal(&self) -> [isize; 3] {
+        FACES[self.face()].normal
+    }
+
+    /// From 0 (fully occluded) to 3 (open)
+    pub fn ao(&self) -> u8 {
+        (self.data >> Self::AO_SHIFT & Self::AO_MASK) as u8
+    }
+
+    pub fn block(&self) -> Voxel {
+        self.block as Voxel
+    }
+}
+
+/// 4 vertices per quad, drawn as triangles with the indices of `quad_indices`
+/// that all chunks share
+#[derive(Default)]
+pub struct ChunkMesh {
+    pub vertices: Vec<ChunkMeshVertex>,
+}
+
+impl ChunkMesh {
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// GPU memory taken by the vertex buffer, the index buffer is shared
+    pub fn size_in_bytes(&self) -> usize {
+        self.vertices.len() * size_of::<ChunkMeshVertex>()
+    }
+
+    fn push_quad(&mut self, quad: &Quad) {
+        let Face {
+            axes: [u, v],
+            corners,
+            ..
+        } = &FACES[quad.face];
+        let mut vertices = [0, 1, 2, 3].map(|i| {
+            let mut pos = corners[i];
+            pos[*u] *= quad.size[0];
+            pos[*v] *= quad.size[1];
+            let pos = [0, 1, 2].map(|i| pos[i] + quad.pos[i]);
+            ChunkMeshVertex::new(pos, quad.face, quad.ao[i], quad.block)
+        });
+        // split along the darker diagonal, otherwise occlusion looks anisotropic.
+        // The indices split along the first and third vertex
+        let [a0, a1, a2, a3] = quad.ao;
+        if a0 + a2 > a1 + a3 {
+            vertices.rotate_left(1);
+        }
+        self.vertices.extend(vertices);
+    }
+}
+
+/// Two triangles for each of the first `quads` quads of any chunk mesh
+pub fn quad_indices(quads: usize) -> impl Iterator<Item = u32> {
+    (0..quads as u32).flat_map(|quad| QUAD_INDICES.map(|index| 4 * quad + index))
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshingStrategy {
+    /// Every face of every voxel
+    Naive,
+    /// Faces hidden by an opaque neighbour are skipped
+    #[default]
+    Culled,
+    /// Culled faces merged into rectangles of the same block
+    Greedy,
+}
+
+/// How faces towards a chunk that is not loaded are treated
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingNeighbour {
+    /// Border faces are visible
+    #[default]
+    Air,
+    /// Border faces are hidden until the neighbour shows up
+    Solid,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeshSettings {
+    pub strategy: MeshingStrategy,
+    pub missing_neighbour: MissingNeighbour,
+    /// Darken face corners next to opaque voxels
+    pub ambient_occlusion: bool,
+}
+
+impl Default for MeshSettings {
+    fn default() -> Self {
+        Self {
+            strategy: MeshingStrategy::default(),
+            missing_neighbour: MissingNeighbour::default(),
+            ambient_occlusion: true,
+        }
+    }
+}
+
+/// Six chunks sharing a side with the meshed one
+#[derive(Default)]
+pub struct ChunkNeighbours<'a> {
+    chunks: [Option<&'a Chunk>; 6],
+}
+
+impl<'a> ChunkNeighbours<'a> {
+    /// `neighbour` is called with the offset of every adjacent chunk index
+    pub fn from_fn(mut neighbour: impl FnMut([isize; 3]) -> Option<&'a Chunk>) -> Self {
+        Self {
+            chunks: FACES.each_ref().map(|face| neighbour(face.normal)),
+        }
+    }
+
+    pub fn from_owned(chunks: &'a [Option<Chunk>; 6]) -> Self {
+        Self {
+            chunks: chunks.each_ref().map(Option::as_ref),
+        }
+    }
+
+    /// Copies of the neighbours, e.g. to mesh on another thread
+    pub fn cloned(&self) -> [Option<Chunk>; 6] {
+        self.chunks.map(|chunk| chunk.cloned())
+    }
+}
+
+pub fn mesh(chunk: &Chunk, registry: &BlockRegistry) -> ChunkMesh {
+    mesh_with(chunk, registry, MeshingStrategy::default())
+}
+
+pub fn mesh_with(chunk: &Chunk, registry: &BlockRegistry, strategy: MeshingStrategy) -> ChunkMesh {
+    let settings = MeshSettings {
+        strategy,
+        ..Default::default()
+    };
+    mesh_with_neighbours(chunk, &ChunkNeighbours::default(), registry, settings)
+}
+
+pub fn mesh_with_neighbours(
+    chunk: &Chunk,
+    neighbours: &ChunkNeighbours,
+    registry: &BlockRegistry,
+    settings: MeshSettings,
+) -> ChunkMesh {
+    let volume = Volume {
+        chunk,
+        neighbours,
+        registry,
+        missing: settings.missing_neighbour,
+        ambient_occlusion: settings.ambient_occlusion,
+    };
+    let quads = match settings.strategy {
+        MeshingStrategy::Naive => face_quads(&volume, false),
+        MeshingStrategy::Culled => face_quads(&volume, true),
+        MeshingStrategy::Greedy => greedy_quads(&volume),
+    };
+    let mut mesh = ChunkMesh::default();
+    quads.iter().for_each(|quad| mesh.push_quad(quad));
+    mesh
+}
+
+/// Rectangle on a voxel face plane.
+/// `pos` is the voxel at the minimal corner, `size` spans the face tangent axes
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Quad {
+    face: usize,
+    pos: [usize; 3],
+    size: [usize; 2],
+    block: Voxel,
+    /// Per corner of the face
+    ao: [u8; 4],
+}
+
+/// Meshed chunk together with everything around it
+struct Volume<'a> {
+    chunk: &'a Chunk,
+    neighbours: &'a ChunkNeighbours<'a>,
+    registry: &'a BlockRegistry,
+    missing: MissingNeighbour,
+    ambient_occlusion: bool,
+}
+
+impl Volume<'_> {
+    /// `pos` may leave the chunk by one voxel through any of its sides,
+    /// voxels of diagonal chunks are treated as missing
+    fn is_opaque(&self, pos: [isize; 3]) -> bool {
+        const D: isize = Chunk::DIMENSIONS as isize;
+        let offset = pos.map(|c| c.div_euclid(D));
+        let chunk = match offset {
+            [0, 0, 0] => Some(self.chunk),
+            _ => FACES
+                .iter()
+                .position(|face| face.normal == offset)
+                .and_then(|face| self.neighbours.chunks[face]),
+        };
+        match chunk {
+            Some(chunk) => {
+                let [x, y, z] = pos.map(|c| c.rem_euclid(D) as usize);
+                self.registry[chunk.get([x, y, z])].opaque
+            }
+            None => self.missing == MissingNeighbour::Solid,
+        }
+    }
+
+    fn is_occluded(&self, pos: [usize; 3], face: usize) -> bool {
+        let normal = FACES[face].normal;
+        self.is_opaque([0, 1, 2].map(|i| pos[i] as isize + normal[i]))
+    }
+
+    /// Occlusion of every face corner by the voxels in front of the face
+    fn face_ao(&self, pos: [usize; 3], face: usize) -> [u8; 4] {
+        const OPEN: u8 = 3;
+        if !self.ambient_occlusion {
+            return [OPEN; 4];
+        }
+        let Face {
+            normal,
+            axes: [u, v],
+            corners,
+        } = &FACES[face];
+        let front = [0, 1, 2].map(|i| pos[i] as isize + normal[i]);
+        corners.map(|corner| {
+            // corners lie on 0 or 1 of the tangent axis, look towards them
+            let du = corner[*u] as isize * 2 - 1;
+            let dv = corner[*v] as isize * 2 - 1;
+            let shifted = |du: isize, dv: isize| {
+                let mut pos = front;
+                pos[*u] += du;
+                pos[*v] += dv;
+                self.is_opaque(pos)
+            };
+            let side_u = shifted(du, 0);
+            let side_v = shifted(0, dv);
+            if side_u && side_v {
+                0
+            } else {
+                OPEN - side_u as u8 - side_v as u8 - shifted(du, dv) as u8
+            }
+        })
+    }
 }
 
-pub fn mesh(chunk: &Chunk) -> Vec<ChunkMeshVertex> {
+fn face_quads(volume: &Volume, cull: bool) -> Vec<Quad> {
     const D: usize = Chunk::DIMENSIONS;
-    let mut mesh = Vec::new();
+    let mut quads = Vec::new();
     for_multi!(0..D, 0..D, 0..D; |x: usize, y: usize, z: usize| {
-        if let Some(color) = chunk.voxels[z][y][x] {
-            mesh.append(&mut voxel_mesh((x, y, z), color));
+        let voxel = volume.chunk.get([x, y, z]);
+        if voxel != AIR {
+            for face in 0..FACES.len() {
+                if !cull || !volume.is_occluded([x, y, z], face) {
+                    let ao = volume.face_ao([x, y, z], face);
+                    quads.push(Quad { face, pos: [x, y, z], size: [1, 1], block: voxel, ao });
+                }
+            }
         }
     });
-    mesh
+    quads
 }
 
-fn voxel_mesh((x, y, z): (usize, usize, usize), color: Color) -> Vec<ChunkMeshVertex> {
-    let shift = [x as f32, y as f32, z as f32];
-    // TODO(optimize): voxel mesh
-    VOXEL_MESH
-        .iter()
-        .map(|vert| ChunkMeshVertex {
-            pos: vert.add(shift),
-            color,
-        })
-        .collect()
+fn greedy_quads(volume: &Volume) -> Vec<Quad> {
+    const D: usize = Chunk::DIMENSIONS;
+    let mut quads = Vec::new();
+    for (face, Face { axes: [u, v], .. }) in FACES.iter().enumerate() {
+        let n = normal_axis(face);
+        for layer in 0..D {
+            let voxel_pos = |i: usize, j: usize| {
+                let mut pos = [0; 3];
+                pos[n] = layer;
+                pos[*u] = i;
+                pos[*v] = j;
+                pos
+            };
+
+            // visible faces of the layer, indexed [j][i] along the tangent axes,
+            // only faces with equal block and occlusion are merged
+            let mut mask: [[Option<(Voxel, _)>; D]; D] = [[None; D]; D];
+            for_multi!(0..D, 0..D; |j: usize, i: usize| {
+                let [x, y, z] = voxel_pos(i, j);
+                let voxel = volume.chunk.get([x, y, z]);
+                if voxel != AIR && !volume.is_occluded([x, y, z], face) {
+                    mask[j][i] = Some((voxel, volume.face_ao([x, y, z], face)));
+                }
+            });
+
+            for j in 0..D {
+                let mut i = 0;
+                while i < D {
+                    let Some((voxel, ao)) = mask[j][i] else {
+                        i += 1;
+                        continue;
+                    };
+                    let width = mask[j][i..]
+                        .iter()
+                        .take_while(|&&other| other == Some((voxel, ao)))
+                        .count();
+                    let height = 1 + mask[j + 1..]
+                        .iter()
+                        .take_while(|row| {
+                            row[i..i + width]
+                                .iter()
+                                .all(|&other| other == Some((voxel, ao)))
+                        })
+                        .count();
+                    mask[j..j + height]
+                        .iter_mut()
+                        .for_each(|row| row[i..i + width].fill(None));
+
+                    quads.push(Quad {
+                        face,
+                        pos: voxel_pos(i, j),
+                        size: [width, height],
+                        block: voxel,
+                        ao,
+                    });
+                    i += width;
+                }
+            }
+        }
+    }
+    quads
 }
 
-const VOXEL_MESH: [Vec3; 36] = [
+fn normal_axis(face: usize) -> usize {
+    FACES[face].normal.iter().position(|&c| c != 0).unwrap()
+}
+
+struct Face {
+    normal: [isize; 3],
+    /// Tangent axes of the face plane
+    axes: [usize; 2],
+    /// In winding order
+    corners: [[usize; 3]; 4],
+}
+
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
+
+const FACES: [Face; 6] = [
     // xz
-    [0.0, 0.0, 0.0],
-    [1.0, 0.0, 0.0],
-    [1.0, 0.0, 1.0],
-    [1.0, 0.0, 1.0],
-    [0.0, 0.0, 1.0],
-    [0.0, 0.0, 0.0],
+    Face {
+        normal: [0, -1, 0],
+        axes: [0, 2],
+        corners: [[0, 0, 0], [1, 0, 0], [1, 0, 1], [0, 0, 1]],
+    },
     // xy
-    [0.0, 0.0, 0.0],
-    [0.0, 1.0, 0.0],
-    [1.0, 1.0, 0.0],
-    [1.0, 1.0, 0.0],
-    [1.0, 0.0, 0.0],
-    [0.0, 0.0, 0.0],
+    Face {
+        normal: [0, 0, -1],
+        axes: [0, 1],
+        corners: [[0, 0, 0], [0, 1, 0], [1, 1, 0], [1, 0, 0]],
+    },
     // yz
-    [0.0, 0.0, 0.0],
-    [0.0, 0.0, 1.0],
-    [0.0, 1.0, 1.0],
-    [0.0, 1.0, 1.0],
-    [0.0, 1.0, 0.0],
-    [0.0, 0.0, 0.0],
+    Face {
+        normal: [-1, 0, 0],
+        axes: [1, 2],
+        corners: [[0, 0, 0], [0, 0, 1], [0, 1, 1], [0, 1, 0]],
+    },
     // xz +y
-    [0.0, 1.0, 0.0],
-    [0.0, 1.0, 1.0],
-    [1.0, 1.0, 1.0],
-    [1.0, 1.0, 1.0],
-    [1.0, 1.0, 0.0],
-    [0.0, 1.0, 0.0],
+    Face {
+        normal: [0, 1, 0],
+        axes: [0, 2],
+        corners: [[0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]],
+    },
     // xy +z
-    [0.0, 0.0, 1.0],
-    [1.0, 0.0, 1.0],
-    [1.0, 1.0, 1.0],
-    [1.0, 1.0, 1.0],
-    [0.0, 1.0, 1.0],
-    [0.0, 0.0, 1.0],
+    Face {
+        normal: [0, 0, 1],
+        axes: [0, 1],
+        corners: [[0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]],
+    },
     // yz +x
-    [1.0, 0.0, 0.0],
-    [1.0, 1.0, 0.0],
-    [1.0, 1.0, 1.0],
-    [1.0, 1.0, 1.0],
-    [1.0, 0.0, 1.0],
-    [1.0, 0.0, 0.0],
+    Face {
+        normal: [1, 0, 0],
+        axes: [1, 2],
+        corners: [[1, 0, 0], [1, 1, 0], [1, 1, 1], [1, 0, 1]],
+    },
 ];
+
+#[cfg(test)]
+mod chunk_mesher_tests {
+    use crate::{
+        for_multi,
+        modules::logic::{
+            block_registry::{Block, BlockRegistry},
+            chunk::Chunk,
+            voxel::{Voxel, AIR},
+        },
+    };
+
+    use super::{
+        face_quads, greedy_quads, mesh, mesh_with, mesh_with_neighbours, quad_indices,
+        ChunkNeighbours, MeshSettings, MeshingStrategy, MissingNeighbour, Quad, Volume, FACES,
+    };
+
+    const D: usize = Chunk::DIMENSIONS;
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const SOLID: Voxel = 1;
+    const RED: Voxel = 2;
+    const GLASS: Voxel = 3;
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::default();
+        registry.register(Block::new("solid", WHITE));
+        registry.register(Block::new("red", [255, 0, 0, 255]));
+        registry.register(Block::new("glass", [255, 255, 255, 100]));
+        registry
+    }
+
+    fn face_count(chunk: &Chunk) -> usize {
+        mesh(chunk, &registry()).quad_count()
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(0, face_count(&Chunk::empty()));
+    }
+
+    #[test]
+    fn test_single_voxel() {
+        let mut chunk = Chunk::empty();
+        chunk.set([7, 6, 5], SOLID);
+        assert_eq!(6, face_count(&chunk));
+    }
+
+    #[test]
+    fn test_two_adjacent() {
+        let mut chunk = Chunk::empty();
+        chunk.set([7, 6, 5], SOLID);
+        chunk.set([8, 6, 5], SOLID);
+        assert_eq!(10, face_count(&chunk));
+    }
+
+    #[test]
+    fn test_translucent_neighbour() {
+        let mut chunk = Chunk::empty();
+        chunk.set([7, 6, 5], SOLID);
+        chunk.set([8, 6, 5], GLASS);
+        // the translucent voxel hides nothing, the solid one hides its shared face
+        assert_eq!(11, face_count(&chunk));
+    }
+
+    #[test]
+    fn test_full_chunk() {
+        let mut chunk = Chunk::empty();
+        for_multi!(0..D, 0..D, 0..D; |x: usize, y: usize, z: usize| {
+            chunk.set([x, y, z], SOLID);
+        });
+        assert_eq!(6 * D * D, face_count(&chunk));
+    }
+
+    #[test]
+    fn test_checkerboard() {
+        let mut chunk = Chunk::empty();
+        for_multi!(0..D, 0..D, 0..D; |x: usize, y: usize, z: usize| {
+            if (x + y + z) % 2 == 0 {
+                chunk.set([x, y, z], SOLID);
+            }
+        });
+        assert_eq!(6 * D * D * D / 2, face_count(&chunk));
+    }
+
+    // every quad split back into unit faces
+    fn unit_faces(quads: &[Quad]) -> Vec<Quad> {
+        let mut faces = Vec::new();
+        for quad in quads {
+            let [u, v] = FACES[quad.face].axes;
+            for_multi!(0..quad.size[0], 0..quad.size[1]; |i: usize, j: usize| {
+                let mut pos = quad.pos;
+                pos[u] += i;
+                pos[v] += j;
+                faces.push(Quad { pos, size: [1, 1], ..*quad });
+            });
+        }
+        faces.sort_by_key(|quad| (quad.face, quad.pos, quad.block));
+        faces
+    }
+
+    fn assert_same_surface(chunk: &Chunk, registry: &BlockRegistry) {
+        let volume = Volume {
+            chunk,
+            neighbours: &ChunkNeighbours::default(),
+            registry,
+            missing: MissingNeighbour::Air,
+            ambient_occlusion: true,
+        };
+        let culled = face_quads(&volume, true);
+        let greedy = greedy_quads(&volume);
+        assert!(greedy.len() <= culled.len());
+        assert_eq!(unit_faces(&culled), unit_faces(&greedy));
+    }
+
+    #[test]
+    fn test_naive() {
+        let mut chunk = Chunk::empty();
+        chunk.set([7, 6, 5], SOLID);
+        chunk.set([8, 6, 5], SOLID);
+        assert_eq!(
+            12,
+            mesh_with(&chunk, &registry(), MeshingStrategy::Naive).quad_count()
+        );
+    }
+
+    #[test]
+    fn test_greedy_full_chunk() {
+        let mut chunk = Chunk::empty();
+        for_multi!(0..D, 0..D, 0..D; |x: usize, y: usize, z: usize| {
+            chunk.set([x, y, z], SOLID);
+        });
+        assert_eq!(
+            6,
+            mesh_with(&chunk, &registry(), MeshingStrategy::Greedy).quad_count()
+        );
+        assert_same_surface(&chunk, &registry());
+    }
+
+    #[test]
+    fn test_greedy_colors() {
+        let mut chunk = Chunk::empty();
+        for_multi!(0..D, 0..D; |x: usize, y: usize| {
+            chunk.set([x, y, 0], if x < D / 2 { SOLID } else { RED });
+        });
+        // two slabs, the shared side between them is not a face
+        assert_eq!(
+            2 * 5,
+            mesh_with(&chunk, &registry(), MeshingStrategy::Greedy).quad_count()
+        );
+        assert_same_surface(&chunk, &registry());
+    }
+
+    #[test]
+    fn test_greedy_surface() {
+        let mut checkerboard = Chunk::empty();
+        for_multi!(0..D, 0..D, 0..D; |x: usize, y: usize, z: usize| {
+            if (x + y + z) % 2 == 0 {
+                checkerboard.set([x, y, z], SOLID);
+            }
+        });
+        assert_same_surface(&checkerboard, &registry());
+
+        let mut registry = registry();
+        let random = Chunk::random(&mut registry);
+        assert_same_surface(&random, &registry);
+        let cat = Chunk::cat(&mut registry);
+        assert_same_surface(&cat, &registry);
+    }
+
+    fn full_chunk() -> Chunk {
+        Chunk::filled(SOLID)
+    }
+
+    #[test]
+    fn test_neighbour_chunks() {
+        let chunk = full_chunk();
+        let neighbour = full_chunk();
+        let neighbours = ChunkNeighbours::from_fn(|offset| match offset {
+            [1, 0, 0] | [0, 0, -1] => Some(&neighbour),
+            _ => None,
+        });
+        for strategy in [MeshingStrategy::Culled, MeshingStrategy::Greedy] {
+            let settings = MeshSettings {
+                strategy,
+                ..Default::default()
+            };
+            let faces =
+                mesh_with_neighbours(&chunk, &neighbours, &registry(), settings).quad_count();
+            let expected = if strategy == MeshingStrategy::Greedy {
+                4
+            } else {
+                4 * D * D
+            };
+            assert_eq!(expected, faces);
+        }
+    }
+
+    #[test]
+    fn test_empty_neighbour() {
+        let mut chunk = Chunk::empty();
+        chunk.set([0, 0, 0], SOLID);
+        let neighbour = Chunk::empty();
+        let neighbours = ChunkNeighbours::from_fn(|_| Some(&neighbour));
+        let settings = MeshSettings {
+            missing_neighbour: MissingNeighbour::Solid,
+            ..Default::default()
+        };
+        assert_eq!(
+            6,
+            mesh_with_neighbours(&chunk, &neighbours, &registry(), settings).quad_count()
+        );
+    }
+
+    #[test]
+    fn test_missing_neighbours() {
+        let chunk = full_chunk();
+        let settings = MeshSettings {
+            missing_neighbour: MissingNeighbour::Solid,
+            ..Default::default()
+        };
+        let neighbours = ChunkNeighbours::default();
+        assert!(mesh_with_neighbours(&chunk, &neighbours, &registry(), settings).is_empty());
+
+        let mut chunk = Chunk::empty();
+        chunk.set([5, 5, 0], SOLID);
+        // only the bottom face touches the missing chunk below
+        assert_eq!(
+            5,
+            mesh_with_neighbours(&chunk, &neighbours, &registry(), settings).quad_count()
+        );
+    }
+
+    #[test]
+    fn test_packing() {
+        let mut chunk = Chunk::empty();
+        chunk.set([D - 1, D - 1, D - 1], SOLID);
+        let mesh = mesh(&chunk, &registry());
+        assert_eq!(6 * 4, mesh.vertices.len());
+        for vertex in &mesh.vertices {
+            let pos = vertex.pos();
+            assert!(pos.iter().all(|&c| c == D as u32 - 1 || c == D as u32));
+            assert_eq!(SOLID, vertex.block());
+        }
+        let faces: Vec<_> = mesh.vertices.iter().map(|vertex| vertex.face()).collect();
+        assert_eq!(vec![0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2], faces[..12]);
+    }
+
+    // unindexed [f32; 3] position and RGBA color, 36 vertices per voxel
+    const LEGACY_VERTEX_SIZE: usize = size_of::<[f32; 3]>() + size_of::<[u8; 4]>();
+
+    #[test]
+    fn test_bytes_per_face() {
+        let mut chunk = Chunk::empty();
+        chunk.set([7, 6, 5], SOLID);
+        let mesh = mesh_with(&chunk, &registry(), MeshingStrategy::Naive);
+
+        let legacy = 6 * LEGACY_VERTEX_SIZE;
+        let packed = mesh.size_in_bytes() / 6;
+        assert_eq!(96, legacy);
+        assert_eq!(32, packed);
+        assert!(packed * 3 <= legacy);
+    }
+
+    fn assert_bytes_per_chunk(chunk: &Chunk, registry: &BlockRegistry) {
+        let mut voxels = 0;
+        for_multi!(0..D, 0..D, 0..D; |x: usize, y: usize, z: usize| {
+            voxels += (chunk.get([x, y, z]) != AIR) as usize;
+        });
+        let legacy = 36 * LEGACY_VERTEX_SIZE * voxels;
+        let greedy = mesh_with(chunk, registry, MeshingStrategy::Greedy).size_in_bytes();
+        assert!(greedy * 4 < legacy);
+    }
+
+    #[test]
+    fn test_bytes_per_chunk() {
+        // isolated voxels of `Chunk::random` only gain the per face saving
+        let mut registry = registry();
+        let cat = Chunk::cat(&mut registry);
+        assert_bytes_per_chunk(&cat, &registry);
+        assert_bytes_per_chunk(&full_chunk(), &registry);
+    }
+
+    // occlusion of the +z face of the voxel at (5, 5, 5) in vertex order
+    fn top_face(chunk: &Chunk, settings: MeshSettings) -> Vec<u8> {
+        let mesh = mesh_with_neighbours(chunk, &ChunkNeighbours::default(), &registry(), settings);
+        let first = mesh
+            .vertices
+            .iter()
+            .position(|vertex| vertex.face() == 4 && vertex.pos()[2] == 6)
+            .unwrap();
+        mesh.vertices[first..first + 4]
+            .iter()
+            .map(|vertex| vertex.ao())
+            .collect()
+    }
+
+    #[test]
+    fn test_ambient_occlusion() {
+        let mut chunk = Chunk::empty();
+        chunk.set([5, 5, 5], SOLID);
+        chunk.set([6, 5, 6], SOLID);
+        assert_eq!(vec![3, 2, 2, 3], top_face(&chunk, MeshSettings::default()));
+
+        // both sides occlude the corner completely, corners 2, 0, 2, 3 start
+        // at the second to split through it
+        chunk.set([5, 4, 6], SOLID);
+        assert_eq!(vec![0, 2, 3, 2], top_face(&chunk, MeshSettings::default()));
+
+        let settings = MeshSettings {
+            ambient_occlusion: false,
+            ..Default::default()
+        };
+        assert_eq!(vec![3, 3, 3, 3], top_face(&chunk, settings));
+    }
+
+    #[test]
+    fn test_flipped_diagonal() {
+        let mut chunk = Chunk::empty();
+        chunk.set([5, 5, 5], SOLID);
+        chunk.set([6, 4, 6], SOLID);
+        // corners 3, 2, 3, 3 start at the second, so the split runs through the dark one
+        assert_eq!(vec![2, 3, 3, 3], top_face(&chunk, MeshSettings::default()));
+        let quads: Vec<_> = quad_indices(2).collect();
+        assert_eq!(vec![0, 1, 2, 2, 3, 0, 4, 5, 6, 6, 7, 4], quads);
+    }
+}
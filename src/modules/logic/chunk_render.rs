@@ -1,7 +1,10 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use vulkano::{
     buffer::BufferContents,
+    descriptor_set::layout::{
+        DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType,
+    },
     pipeline::{
         graphics::{
             color_blend::{
@@ -23,9 +26,21 @@ use vulkano::{
     shader::ShaderStages,
 };
 
-use crate::modules::{math::mat::Mat4x4, renderer::Renderer, shaders};
+use crate::modules::{
+    math::{
+        mat::Mat4x4,
+        vec::{VecMult, VecNorm},
+    },
+    renderer::Renderer,
+    shaders,
+};
 
-use super::chunk_mesher::ChunkMeshVertex;
+use super::{
+    block_registry::BlockRegistry, chunk_mesher::ChunkMeshVertex, light::Light, voxel::Voxel,
+};
+
+/// Binding of the block colors in descriptor set 0
+pub const BLOCK_COLORS_BINDING: u32 = 0;
 
 pub fn chunk_subpass() -> SubpassDescription {
     Renderer::default_subpass()
@@ -85,11 +100,19 @@ pub fn chunk_graphics_pipeline(
     let layout = {
         let push_constant_ranges = vec![PushConstantRange {
             stages: ShaderStages::VERTEX,
-            size: 16 * 4,
+            size: size_of::<ChunkPushConstant>() as u32,
             ..Default::default()
         }];
+        let block_colors = DescriptorSetLayoutBinding {
+            stages: ShaderStages::VERTEX,
+            ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::StorageBuffer)
+        };
+        let set_layout = renderer.descriptor_set_layout(DescriptorSetLayoutCreateInfo {
+            bindings: BTreeMap::from([(BLOCK_COLORS_BINDING, block_colors)]),
+            ..Default::default()
+        });
         let create_info = PipelineLayoutCreateInfo {
-            set_layouts: Default::default(),
+            set_layouts: vec![set_layout],
             push_constant_ranges,
             ..Default::default()
         };
@@ -111,8 +134,32 @@ pub fn chunk_graphics_pipeline(
     }
 }
 
+/// RGBA of every block packed into a u32 as `unpackUnorm4x8` reads it,
+/// indexed by the block ids of the mesh vertices
+pub fn block_colors(registry: &BlockRegistry) -> Vec<u32> {
+    (0..registry.len())
+        .map(|id| u32::from_le_bytes(registry[id as Voxel].color))
+        .collect()
+}
+
 #[derive(BufferContents)]
 #[repr(C)]
 pub struct ChunkPushConstant {
     pub pvm: Mat4x4,
+    /// Normalized direction the light travels in, w is unused
+    pub light_direction: [f32; 4],
+    /// Color scaled by intensity, w is the ambient term
+    pub light_color: [f32; 4],
+}
+
+impl ChunkPushConstant {
+    pub fn new(pvm: Mat4x4, light: &Light) -> Self {
+        let [dx, dy, dz] = light.direction.norm();
+        let [r, g, b] = light.color.mult(light.intensity);
+        Self {
+            pvm,
+            light_direction: [dx, dy, dz, 0.0],
+            light_color: [r, g, b, light.ambient],
+        }
+    }
 }
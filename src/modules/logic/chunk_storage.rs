@@ -0,0 +1,221 @@
+use super::{chunk::Chunk, voxel::Voxel};
+
+const LEN: usize = Chunk::DIMENSIONS * Chunk::DIMENSIONS * Chunk::DIMENSIONS;
+
+/// Voxels of a chunk, compressed against the block types it contains
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkStorage {
+    /// Every voxel is the same, e.g. all air or all stone
+    Uniform(Voxel),
+    /// Palette indices bit-packed into words
+    Packed(PackedStorage),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedStorage {
+    palette: Vec<Voxel>,
+    /// Bits per palette index, always divides 64 so indices never straddle words
+    bits: u32,
+    words: Vec<u64>,
+}
+
+impl ChunkStorage {
+    pub fn get(&self, i: usize) -> Voxel {
+        match self {
+            ChunkStorage::Uniform(voxel) => *voxel,
+            ChunkStorage::Packed(packed) => packed.get(i),
+        }
+    }
+
+    pub fn set(&mut self, i: usize, voxel: Voxel) {
+        match self {
+            ChunkStorage::Uniform(uniform) if *uniform == voxel => (),
+            ChunkStorage::Uniform(uniform) => {
+                let mut packed = PackedStorage::new(*uniform);
+                packed.set(i, voxel);
+                *self = ChunkStorage::Packed(packed);
+            }
+            ChunkStorage::Packed(packed) => packed.set(i, voxel),
+        }
+    }
+
+    /// Drops unused palette entries, a chunk of a single block becomes `Uniform`
+    pub fn compact(&mut self) {
+        if let ChunkStorage::Packed(packed) = self {
+            let compacted = packed.compacted();
+            *self = match compacted.palette[..] {
+                [voxel] => ChunkStorage::Uniform(voxel),
+                _ => ChunkStorage::Packed(compacted),
+            };
+        }
+    }
+
+    /// Bytes taken by the storage, heap included
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                ChunkStorage::Uniform(_) => 0,
+                ChunkStorage::Packed(packed) => {
+                    packed.palette.capacity() * size_of::<Voxel>()
+                        + packed.words.capacity() * size_of::<u64>()
+                }
+            }
+    }
+}
+
+impl PackedStorage {
+    const MIN_BITS: u32 = 1;
+
+    fn new(voxel: Voxel) -> Self {
+        Self::with_bits(vec![voxel], Self::MIN_BITS)
+    }
+
+    fn with_bits(palette: Vec<Voxel>, bits: u32) -> Self {
+        let per_word = (u64::BITS / bits) as usize;
+        Self {
+            palette,
+            bits,
+            words: vec![0; LEN.div_ceil(per_word)],
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    fn locate(&self, i: usize) -> (usize, u32) {
+        let per_word = (u64::BITS / self.bits) as usize;
+        (i / per_word, (i % per_word) as u32 * self.bits)
+    }
+
+    fn index(&self, i: usize) -> usize {
+        let (word, shift) = self.locate(i);
+        (self.words[word] >> shift & self.mask()) as usize
+    }
+
+    fn set_index(&mut self, i: usize, index: usize) {
+        let (word, shift) = self.locate(i);
+        let mask = self.mask();
+        self.words[word] = self.words[word] & !(mask << shift) | (index as u64 & mask) << shift;
+    }
+
+    fn get(&self, i: usize) -> Voxel {
+        self.palette[self.index(i)]
+    }
+
+    fn set(&mut self, i: usize, voxel: Voxel) {
+        let index = match self.palette.iter().position(|&entry| entry == voxel) {
+            Some(index) => index,
+            None => {
+                if self.palette.len() == 1 << self.bits {
+                    // reuse entries nothing points to before growing
+                    *self = self.compacted();
+                }
+                if self.palette.len() == 1 << self.bits {
+                    *self = self.repacked(self.bits * 2);
+                }
+                self.palette.push(voxel);
+                self.palette.len() - 1
+            }
+        };
+        self.set_index(i, index);
+    }
+
+    fn repacked(&self, bits: u32) -> Self {
+        let mut repacked = Self::with_bits(self.palette.clone(), bits);
+        (0..LEN).for_each(|i| repacked.set_index(i, self.index(i)));
+        repacked
+    }
+
+    fn compacted(&self) -> Self {
+        let mut used = vec![false; self.palette.len()];
+        (0..LEN).for_each(|i| used[self.index(i)] = true);
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (index, &voxel) in self.palette.iter().enumerate() {
+            if used[index] {
+                remap[index] = palette.len();
+                palette.push(voxel);
+            }
+        }
+
+        let mut bits = Self::MIN_BITS;
+        while 1 << bits < palette.len() {
+            bits *= 2;
+        }
+        let mut compacted = Self::with_bits(palette, bits);
+        (0..LEN).for_each(|i| compacted.set_index(i, remap[self.index(i)]));
+        compacted
+    }
+}
+
+#[cfg(test)]
+mod chunk_storage_tests {
+    use crate::modules::logic::voxel::{Voxel, AIR};
+
+    use super::{ChunkStorage, LEN};
+
+    const DENSE: usize = LEN * size_of::<Voxel>();
+
+    // cheap deterministic noise
+    fn pattern(i: usize, kinds: usize) -> Voxel {
+        (i.wrapping_mul(2654435761) >> 7) as Voxel % kinds as Voxel
+    }
+
+    fn fill(kinds: usize) -> ChunkStorage {
+        let mut storage = ChunkStorage::Uniform(AIR);
+        (0..LEN).for_each(|i| storage.set(i, pattern(i, kinds)));
+        storage
+    }
+
+    #[test]
+    fn test_get_set() {
+        for kinds in [1, 2, 3, 17, 300, 4000] {
+            let storage = fill(kinds);
+            assert!((0..LEN).all(|i| storage.get(i) == pattern(i, kinds)));
+        }
+    }
+
+    #[test]
+    fn test_uniform() {
+        let mut storage = ChunkStorage::Uniform(7);
+        storage.set(5, 7);
+        assert_eq!(ChunkStorage::Uniform(7), storage);
+
+        storage.set(5, AIR);
+        assert_eq!(AIR, storage.get(5));
+        assert_eq!(7, storage.get(6));
+
+        storage.set(5, 7);
+        storage.compact();
+        assert_eq!(ChunkStorage::Uniform(7), storage);
+    }
+
+    #[test]
+    fn test_palette_reuse() {
+        let mut storage = ChunkStorage::Uniform(AIR);
+        // only two blocks are ever present at once
+        for voxel in 1..100 {
+            (0..LEN).for_each(|i| storage.set(i, voxel));
+        }
+        assert!(storage.memory_usage() * 8 < DENSE);
+        storage.compact();
+        assert_eq!(ChunkStorage::Uniform(99), storage);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let air = ChunkStorage::Uniform(AIR);
+        assert!(air.memory_usage() < 64);
+
+        for (kinds, ratio) in [(2, 16), (4, 8), (16, 4), (256, 2)] {
+            let storage = fill(kinds);
+            let usage = storage.memory_usage();
+            assert!(usage <= DENSE / ratio + 1024);
+        }
+        // worst case is dense plus the palette
+        let usage = fill(4000).memory_usage();
+        assert!(usage < DENSE * 5 / 4);
+    }
+}
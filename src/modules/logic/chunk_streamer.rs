@@ -0,0 +1,377 @@
+use std::{collections::HashSet, fmt};
+
+use super::{
+    block_registry::BlockRegistry,
+    chunk::Chunk,
+    region::{RecoveredRegion, RegionError},
+    scene::{ChunkIndex, Scene},
+};
+
+/// Provides chunks that are not loaded yet
+pub trait ChunkSource {
+    /// `None` means the chunk is all air
+    fn load(
+        &mut self,
+        idx: ChunkIndex,
+        registry: &mut BlockRegistry,
+    ) -> Result<Option<Chunk>, RegionError>;
+
+    /// Receives edited chunks before they are unloaded
+    fn save(
+        &mut self,
+        _chunks: &[(ChunkIndex, &Chunk)],
+        _registry: &BlockRegistry,
+    ) -> Result<(), RegionError> {
+        Ok(())
+    }
+
+    /// Damaged files the source replaced since the last call
+    fn take_recovered(&mut self) -> Vec<RecoveredRegion> {
+        Vec::new()
+    }
+}
+
+impl<F: FnMut(ChunkIndex) -> Option<Chunk>> ChunkSource for F {
+    fn load(
+        &mut self,
+        idx: ChunkIndex,
+        _registry: &mut BlockRegistry,
+    ) -> Result<Option<Chunk>, RegionError> {
+        Ok(self(idx))
+    }
+}
+
+/// Nothing is lost in either case
+#[derive(Debug)]
+pub enum StreamError {
+    /// Far edited chunks stay loaded and are saved again on the next update
+    Save(RegionError),
+    /// The chunk stays unloaded rather than being generated over the saved one.
+    /// It is requested again once it comes back into range
+    Load(ChunkIndex, RegionError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Save(error) => write!(f, "failed to save chunks, {error}"),
+            StreamError::Load(idx, error) => write!(f, "failed to load chunk {idx:?}, {error}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreamingSettings {
+    /// In chunks, every chunk this close to the camera is loaded
+    pub load_radius: isize,
+    /// In chunks, must not be smaller than `load_radius`.
+    /// Chunks in between stay so moving back and forth does not reload them
+    pub unload_radius: isize,
+    /// Limits the time a single update may take
+    pub loads_per_update: usize,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            load_radius: 4,
+            unload_radius: 6,
+            loads_per_update: 8,
+        }
+    }
+}
+
+/// Keeps the chunks around the camera of a scene loaded
+pub struct ChunkStreamer {
+    source: Box<dyn ChunkSource>,
+    settings: StreamingSettings,
+    /// Chunks requested from the source, including the ones it had none for
+    loaded: HashSet<ChunkIndex>,
+}
+
+impl ChunkStreamer {
+    pub fn new(source: impl ChunkSource + 'static, settings: StreamingSettings) -> Self {
+        debug_assert!(settings.load_radius <= settings.unload_radius);
+        Self {
+            source: Box::new(source),
+            settings,
+            loaded: HashSet::new(),
+        }
+    }
+
+    /// Loads the closest missing chunks and unloads the far ones
+    pub fn update(&mut self, scene: &mut Scene) -> Vec<StreamError> {
+        let mut errors = Vec::new();
+        let center = Self::camera_chunk(scene);
+        let distance2 =
+            |idx: ChunkIndex| -> isize { (0..3).map(|i| (idx[i] - center[i]).pow(2)).sum() };
+
+        let unload = self.settings.unload_radius.pow(2);
+        self.loaded.retain(|idx| distance2(*idx) <= unload);
+        let far: Vec<_> = scene
+            .get_chunks()
+            .map(|(idx, _)| *idx)
+            .filter(|idx| distance2(*idx) > unload)
+            .collect();
+        let edited: Vec<_> = far
+            .iter()
+            .filter(|idx| scene.is_modified(**idx))
+            .map(|idx| (*idx, scene.get_chunk(*idx).unwrap()))
+            .collect();
+        let mut unsaved = false;
+        if !edited.is_empty() {
+            if let Err(error) = self.source.save(&edited, scene.registry()) {
+                errors.push(StreamError::Save(error));
+                unsaved = true;
+            }
+        }
+        for idx in far {
+            // edits are only dropped once they are saved
+            if !(unsaved && scene.is_modified(idx)) {
+                scene.remove_chunk(idx);
+            }
+        }
+
+        let r = self.settings.load_radius;
+        let mut missing = Vec::new();
+        for x in -r..=r {
+            for y in -r..=r {
+                for z in -r..=r {
+                    let idx = [center[0] + x, center[1] + y, center[2] + z];
+                    if distance2(idx) <= r * r && !self.loaded.contains(&idx) {
+                        missing.push(idx);
+                    }
+                }
+            }
+        }
+        missing.sort_by_key(|idx| distance2(*idx));
+        for idx in missing.into_iter().take(self.settings.loads_per_update) {
+            self.loaded.insert(idx);
+            // chunks edited before they were streamed in are kept
+            if scene.get_chunk(idx).is_some() {
+                continue;
+            }
+            match self.source.load(idx, scene.registry_mut()) {
+                Ok(Some(chunk)) => {
+                    scene.insert_chunk(idx, chunk);
+                }
+                Ok(None) => (),
+                Err(error) => errors.push(StreamError::Load(idx, error)),
+            }
+        }
+        errors
+    }
+
+    /// Hands every edited chunk to the source, e.g. before exiting.
+    /// They stay modified if saving fails
+    pub fn save_modified(&mut self, scene: &mut Scene) -> Result<(), RegionError> {
+        let edited: Vec<_> = scene
+            .get_chunks()
+            .filter(|(idx, _)| scene.is_modified(**idx))
+            .map(|(idx, chunk)| (*idx, chunk))
+            .collect();
+        if !edited.is_empty() {
+            self.source.save(&edited, scene.registry())?;
+        }
+        scene.take_modified();
+        Ok(())
+    }
+
+    /// Damaged files the source replaced since the last call
+    pub fn take_recovered(&mut self) -> Vec<RecoveredRegion> {
+        self.source.take_recovered()
+    }
+
+    fn camera_chunk(scene: &Scene) -> ChunkIndex {
+        let pos = scene.camera.borrow().pos;
+        Scene::locate(pos.map(|c| c.floor() as isize)).0
+    }
+}
+
+#[cfg(test)]
+mod chunk_streamer_tests {
+    use std::{
+        cell::{Cell, RefCell},
+        io,
+        rc::Rc,
+    };
+
+    use crate::modules::logic::{
+        block_registry::BlockRegistry,
+        chunk::Chunk,
+        region::RegionError,
+        scene::{ChunkIndex, Scene},
+    };
+
+    use super::{ChunkSource, ChunkStreamer, StreamError, StreamingSettings};
+
+    const SETTINGS: StreamingSettings = StreamingSettings {
+        load_radius: 1,
+        unload_radius: 2,
+        loads_per_update: 100,
+    };
+
+    fn scene_at(pos: [f32; 3]) -> Scene {
+        let scene = Scene::new(BlockRegistry::default());
+        scene.camera.borrow_mut().pos = pos;
+        scene
+    }
+
+    #[test]
+    fn test_load_radius() {
+        let mut scene = scene_at([-1.0, 40.0, 0.0]);
+        let mut streamer = ChunkStreamer::new(|_| Some(Chunk::filled(1)), SETTINGS);
+        streamer.update(&mut scene);
+        // the center and its 6 face neighbours
+        assert_eq!(7, scene.get_chunks().len());
+        assert!(scene.get_chunk([-1, 1, 0]).is_some());
+        assert!(scene.get_chunk([-1, 2, 0]).is_some());
+        assert!(scene.get_chunk([0, 2, 0]).is_none());
+    }
+
+    #[test]
+    fn test_load_limit() {
+        let loads = Rc::new(RefCell::new(Vec::new()));
+        let source = {
+            let loads = loads.clone();
+            move |idx| {
+                loads.borrow_mut().push(idx);
+                None
+            }
+        };
+        let mut scene = scene_at([0.0; 3]);
+        let mut streamer = ChunkStreamer::new(
+            source,
+            StreamingSettings {
+                loads_per_update: 3,
+                ..SETTINGS
+            },
+        );
+        streamer.update(&mut scene);
+        assert_eq!(vec![[0, 0, 0]], loads.borrow()[..1]);
+        assert_eq!(3, loads.borrow().len());
+        streamer.update(&mut scene);
+        streamer.update(&mut scene);
+        assert_eq!(7, loads.borrow().len());
+        // empty chunks are not requested again
+        streamer.update(&mut scene);
+        assert_eq!(7, loads.borrow().len());
+        assert_eq!(0, scene.get_chunks().len());
+    }
+
+    #[test]
+    fn test_unload_hysteresis() {
+        let mut scene = scene_at([0.0; 3]);
+        let mut streamer = ChunkStreamer::new(|_| Some(Chunk::filled(1)), SETTINGS);
+        streamer.update(&mut scene);
+        scene.take_dirty();
+
+        // [-1, 0, 0] is 2 chunks away, still inside the unload radius
+        scene.camera.borrow_mut().pos = [40.0, 0.0, 0.0];
+        streamer.update(&mut scene);
+        assert!(scene.get_chunk([-1, 0, 0]).is_some());
+        assert!(scene.get_chunk([2, 0, 0]).is_some());
+
+        scene.camera.borrow_mut().pos = [80.0, 0.0, 0.0];
+        streamer.update(&mut scene);
+        assert!(scene.get_chunk([-1, 0, 0]).is_none());
+        assert!(scene.take_dirty().contains(&[-1, 0, 0]));
+
+        // unloaded chunks come back once in range again
+        scene.camera.borrow_mut().pos = [0.0; 3];
+        streamer.update(&mut scene);
+        assert!(scene.get_chunk([-1, 0, 0]).is_some());
+    }
+
+    /// Records saved chunks, saving fails while `fail` is set.
+    /// Clones share both
+    #[derive(Default, Clone)]
+    struct Recording {
+        saved: Rc<RefCell<Vec<ChunkIndex>>>,
+        fail: Rc<Cell<bool>>,
+    }
+
+    impl ChunkSource for Recording {
+        fn load(
+            &mut self,
+            idx: ChunkIndex,
+            _: &mut BlockRegistry,
+        ) -> Result<Option<Chunk>, RegionError> {
+            match idx {
+                [0, 0, 5] => Err(RegionError::Corrupt("checksum mismatch".into())),
+                _ => Ok(Some(Chunk::filled(1))),
+            }
+        }
+
+        fn save(
+            &mut self,
+            chunks: &[(ChunkIndex, &Chunk)],
+            _: &BlockRegistry,
+        ) -> Result<(), RegionError> {
+            if self.fail.get() {
+                return Err(RegionError::Io(io::Error::other("disk full")));
+            }
+            self.saved
+                .borrow_mut()
+                .extend(chunks.iter().map(|(idx, _)| *idx));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_save_edited() {
+        let recording = Recording::default();
+        let saved = &recording.saved;
+        let mut scene = scene_at([0.0; 3]);
+        let mut streamer = ChunkStreamer::new(recording.clone(), SETTINGS);
+        assert!(streamer.update(&mut scene).is_empty());
+        scene.set_voxel([-5, 0, 0], 2);
+        scene.set_voxel([5, 0, 0], 2);
+
+        scene.camera.borrow_mut().pos = [80.0, 0.0, 0.0];
+        assert!(streamer.update(&mut scene).is_empty());
+        assert_eq!(vec![[-1, 0, 0]], *saved.borrow());
+
+        streamer.save_modified(&mut scene).unwrap();
+        assert_eq!(vec![[-1, 0, 0], [0, 0, 0]], *saved.borrow());
+        streamer.save_modified(&mut scene).unwrap();
+        assert_eq!(2, saved.borrow().len());
+    }
+
+    #[test]
+    fn test_failed_save() {
+        let recording = Recording::default();
+        let (saved, fail) = (&recording.saved, &recording.fail);
+        let mut scene = scene_at([0.0; 3]);
+        let mut streamer = ChunkStreamer::new(recording.clone(), SETTINGS);
+        streamer.update(&mut scene);
+        scene.set_voxel([-5, 0, 0], 2);
+        fail.set(true);
+
+        // the edited chunk stays until it is saved, clean ones are unloaded
+        scene.camera.borrow_mut().pos = [80.0, 0.0, 0.0];
+        let errors = streamer.update(&mut scene);
+        assert!(matches!(errors[..], [StreamError::Save(_)]));
+        assert!(scene.is_modified([-1, 0, 0]));
+        assert!(scene.get_chunk([0, 1, 0]).is_none());
+        assert!(streamer.save_modified(&mut scene).is_err());
+        assert!(scene.is_modified([-1, 0, 0]));
+
+        fail.set(false);
+        assert!(streamer.update(&mut scene).is_empty());
+        assert!(scene.get_chunk([-1, 0, 0]).is_none());
+        assert_eq!(vec![[-1, 0, 0]], *saved.borrow());
+    }
+
+    #[test]
+    fn test_failed_load() {
+        let mut scene = scene_at([0.0, 0.0, 170.0]);
+        let mut streamer = ChunkStreamer::new(Recording::default(), SETTINGS);
+        let errors = streamer.update(&mut scene);
+        assert!(matches!(errors[..], [StreamError::Load([0, 0, 5], _)]));
+        // neither generated nor requested again while in range
+        assert!(scene.get_chunk([0, 0, 5]).is_none());
+        assert!(scene.get_chunk([0, 0, 4]).is_some());
+        assert!(streamer.update(&mut scene).is_empty());
+    }
+}
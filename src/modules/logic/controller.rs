@@ -1,26 +1,77 @@
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
     rc::Rc,
     sync::{mpsc::Receiver, Arc},
 };
 
 use winit::{
-    event::{DeviceEvent, Event, WindowEvent},
+    event::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
     window::Window,
 };
 
 use crate::modules::{math::vec::VecAdd, renderer::Renderer, utility::framerate::Framerate};
 
-use super::{key_input::KeyInputHelper, render_controller::RenderController, scene::Scene};
+use super::{
+    biome::{self, DEFAULT_BIOMES},
+    block_registry::BlockRegistry,
+    brush::{self, Shape},
+    chunk_streamer::{ChunkStreamer, StreamingSettings},
+    density_generator::{DensityGenerator, DensitySettings},
+    key_input::KeyInputHelper,
+    raycast::{self, RayHit},
+    region::{PersistentWorld, RegionStorage},
+    render_controller::RenderController,
+    scene::{Scene, WorldPos},
+    schematic::Schematic,
+    structures::{Decorated, Tree},
+    voxel::{Voxel, AIR},
+};
+
+/// Furthest voxel the camera can edit
+const REACH: f32 = 8.0;
+const MAX_BRUSH_SIZE: isize = 16;
+/// Flood fills stay this close to the clicked voxel
+const FLOOD_REACH: isize = 32;
+/// Bytes of voxel edits kept for undo
+const HISTORY_MEMORY_LIMIT: usize = 128 << 20;
 
 pub struct Controller {
     window: Arc<Window>,
     window_events: Receiver<WindowEvent>,
     device_events: Receiver<DeviceEvent>,
 
-    scene: Rc<Scene>,
+    scene: Rc<RefCell<Scene>>,
+    streamer: ChunkStreamer,
     render_controller: RenderController,
+
+    /// Placed with the right mouse button
+    selected: Voxel,
+    tool: Tool,
+    /// Radius of the brush tools
+    brush_size: isize,
+    /// First click of a line
+    line_start: Option<WorldPos>,
+    /// Corners set with the left and right mouse button
+    selection: [Option<WorldPos>; 2],
+    clipboard: Option<Schematic>,
+}
+
+/// What the mouse buttons edit, picked with the number keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Voxel,
+    Box,
+    Sphere,
+    Cylinder,
+    /// Between two clicks
+    Line,
+    /// Connected voxels of the targeted block
+    Flood,
+    /// The targeted block around the target
+    Replace,
+    /// Corners of the region to copy
+    Select,
 }
 
 impl Controller {
@@ -30,14 +81,218 @@ impl Controller {
         window_events: Receiver<WindowEvent>,
         device_events: Receiver<DeviceEvent>,
     ) -> Self {
-        let scene = Rc::new(Scene::default());
+        let mut registry = BlockRegistry::default();
+        let biomes = biome::parse_biomes(DEFAULT_BIOMES, &mut registry)
+            .unwrap_or_else(|error| panic!("Invalid default biomes, {error}"));
+        let terrain = DensityGenerator::with_biomes(0, DensitySettings::default(), biomes);
+        let generator = Decorated::new(0, terrain, Tree::register(&mut registry));
+        let source = PersistentWorld {
+            storage: RegionStorage::new("world"),
+            generator,
+        };
+
+        let selected = registry.id("stone").unwrap_or(1);
+        let mut scene = Scene::new(registry);
+        scene.history_mut().set_memory_limit(HISTORY_MEMORY_LIMIT);
+        scene.camera.borrow_mut().pos = [0.0, 0.0, 32.0];
+        let scene = Rc::new(RefCell::new(scene));
         Self {
             window,
             window_events,
             device_events,
             scene: scene.clone(),
+            streamer: ChunkStreamer::new(source, StreamingSettings::default()),
             render_controller: RenderController::new(renderer, scene),
+            selected,
+            tool: Tool::Voxel,
+            brush_size: 2,
+            line_start: None,
+            selection: [None; 2],
+            clipboard: None,
+        }
+    }
+
+    /// Voxel the camera looks at
+    fn target(&self) -> Option<RayHit> {
+        let scene = self.scene.borrow();
+        let camera = scene.camera.borrow();
+        raycast::raycast(&scene, camera.pos, camera.forward(), REACH)
+    }
+
+    /// One undo step per click
+    fn edit(&mut self, button: MouseButton) {
+        self.scene.borrow_mut().begin_transaction();
+        self.apply_tool(button);
+        self.scene.borrow_mut().commit_transaction();
+    }
+
+    /// Left removes with the current tool, right places the selected block
+    fn apply_tool(&mut self, button: MouseButton) {
+        let Some(hit) = self.target() else {
+            return;
+        };
+        if self.tool == Tool::Select {
+            match button {
+                MouseButton::Left => self.selection[0] = Some(hit.pos),
+                MouseButton::Right => self.selection[1] = Some(hit.pos),
+                _ => (),
+            }
+            return;
+        }
+        let voxel = match button {
+            MouseButton::Left => AIR,
+            // the camera would end up inside the voxel without a hit face
+            MouseButton::Right if hit.normal != [0; 3] => self.selected,
+            _ => return,
+        };
+        // removing starts at the hit voxel, placing in front of its face
+        let pos = if voxel == AIR { hit.pos } else { hit.adjacent() };
+        let size = self.brush_size;
+        let mut scene = self.scene.borrow_mut();
+        let shape = match self.tool {
+            Tool::Voxel => Shape::Box { a: pos, b: pos },
+            Tool::Box => Shape::Box {
+                a: pos.map(|c| c - size),
+                b: pos.map(|c| c + size),
+            },
+            Tool::Sphere => Shape::Sphere {
+                center: pos,
+                radius: size,
+            },
+            Tool::Cylinder => Shape::Cylinder {
+                base: pos,
+                radius: size,
+                height: 2 * size + 1,
+            },
+            Tool::Line => match self.line_start.take() {
+                Some(from) => Shape::Line { from, to: pos },
+                None => {
+                    self.line_start = Some(pos);
+                    return;
+                }
+            },
+            // both repaint the targeted block
+            Tool::Flood => {
+                let bounds = [
+                    hit.pos.map(|c| c - FLOOD_REACH),
+                    hit.pos.map(|c| c + FLOOD_REACH),
+                ];
+                brush::flood_fill(&mut scene, hit.pos, voxel, bounds);
+                return;
+            }
+            Tool::Select => return,
+            Tool::Replace => {
+                let from = scene.get_voxel(hit.pos);
+                let shape = Shape::Sphere {
+                    center: hit.pos,
+                    radius: size,
+                };
+                brush::replace(&mut scene, &shape, from, voxel);
+                return;
+            }
+        };
+        brush::fill(&mut scene, &shape, voxel);
+    }
+
+    /// Selection into the clipboard, unless it is too large
+    fn copy(&mut self) {
+        let [Some(a), Some(b)] = self.selection else {
+            return;
+        };
+        if let Some(clipboard) = Schematic::copy(&self.scene.borrow(), a, b) {
+            self.clipboard = Some(clipboard);
+        }
+    }
+
+    /// Clipboard in front of the targeted face, one undo step
+    fn paste(&mut self) {
+        let (Some(clipboard), Some(hit)) = (&self.clipboard, self.target()) else {
+            return;
+        };
+        let mut scene = self.scene.borrow_mut();
+        scene.begin_transaction();
+        clipboard.paste(&mut scene, hit.adjacent(), false);
+        scene.commit_transaction();
+    }
+
+    /// Tool, brush size, undo and clipboard keys
+    fn key_pressed(&mut self, key: KeyCode, control: bool) {
+        let tool = match key {
+            KeyCode::KeyZ if control => {
+                self.scene.borrow_mut().undo();
+                return;
+            }
+            KeyCode::KeyY if control => {
+                self.scene.borrow_mut().redo();
+                return;
+            }
+            KeyCode::KeyC if control => {
+                self.copy();
+                return;
+            }
+            KeyCode::KeyV if control => {
+                self.paste();
+                return;
+            }
+            // around the up axis and across x
+            KeyCode::KeyR | KeyCode::KeyM => {
+                if let Some(clipboard) = &mut self.clipboard {
+                    *clipboard = match key {
+                        KeyCode::KeyR => clipboard.rotated(2, 1),
+                        _ => clipboard.mirrored(0),
+                    };
+                }
+                return;
+            }
+            KeyCode::Digit1 => Tool::Voxel,
+            KeyCode::Digit2 => Tool::Box,
+            KeyCode::Digit3 => Tool::Sphere,
+            KeyCode::Digit4 => Tool::Cylinder,
+            KeyCode::Digit5 => Tool::Line,
+            KeyCode::Digit6 => Tool::Flood,
+            KeyCode::Digit7 => Tool::Replace,
+            KeyCode::Digit8 => Tool::Select,
+            KeyCode::BracketLeft | KeyCode::BracketRight => {
+                let step = if key == KeyCode::BracketLeft { -1 } else { 1 };
+                self.brush_size = (self.brush_size + step).clamp(1, MAX_BRUSH_SIZE);
+                return;
+            }
+            _ => return,
+        };
+        self.tool = tool;
+        self.line_start = None;
+    }
+
+    /// Steps through the registered blocks, skipping air
+    fn cycle_selected(&mut self, steps: isize) {
+        let scene = self.scene.borrow();
+        let registry = scene.registry();
+        let blocks = registry.len() as isize - 1;
+        if blocks < 1 || steps == 0 {
+            return;
         }
+        let index = (self.selected as isize - 1 + steps).rem_euclid(blocks);
+        self.selected = index as Voxel + 1;
+    }
+
+    /// Editing state in the window title
+    fn show_status(&self) {
+        let scene = self.scene.borrow();
+        let mut status = vec![
+            format!("Tool: {:?}", self.tool),
+            format!("Size: {}", self.brush_size),
+            format!("Block: {}", scene.registry()[self.selected].name),
+        ];
+        if self.tool == Tool::Select {
+            let corner = |pos: Option<WorldPos>| pos.map_or("-".into(), |pos| format!("{pos:?}"));
+            let [a, b] = self.selection.map(corner);
+            status.push(format!("Selection: {a} to {b}"));
+        }
+        if let Some(clipboard) = &self.clipboard {
+            let [x, y, z] = clipboard.size();
+            status.push(format!("Clipboard: {x}x{y}x{z}"));
+        }
+        self.window.set_title(&status.join(" | "));
     }
 
     pub fn main_loop(&mut self) {
@@ -46,12 +301,14 @@ impl Controller {
         let mut framerate = Framerate::new(Some(60.0));
         let mut fixed = Framerate::new(Some(60.0));
         let mut console_stat = Framerate::new(Some(1.0));
+        self.show_status();
 
         'main: loop {
             let mut redraw_request = false;
             let mut resized = Option::None;
 
-            let window_events = self.window_events.try_iter();
+            // collected, handling them may need `self` mutably
+            let window_events: Vec<_> = self.window_events.try_iter().collect();
             for event in window_events {
                 use WindowEvent::*;
                 match event {
@@ -70,7 +327,14 @@ impl Controller {
                         event,
                         is_synthetic,
                     } => {
-                        // TODO
+                        if event.state.is_pressed() && !event.repeat {
+                            if let PhysicalKey::Code(key) = event.physical_key {
+                                let control = input.is_pressed(KeyCode::ControlLeft)
+                                    || input.is_pressed(KeyCode::ControlRight);
+                                self.key_pressed(key, control);
+                                self.show_status();
+                            }
+                        }
                         input.input(event);
                     }
                     CursorMoved {
@@ -90,14 +354,22 @@ impl Controller {
                         delta,
                         phase,
                     } => {
-                        // TODO
+                        let lines = match delta {
+                            MouseScrollDelta::LineDelta(_, y) => y,
+                            MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
+                        };
+                        self.cycle_selected(lines.signum() as isize);
+                        self.show_status();
                     }
                     MouseInput {
                         device_id,
                         state,
                         button,
                     } => {
-                        // TODO
+                        if state == ElementState::Pressed {
+                            self.edit(button);
+                            self.show_status();
+                        }
                     }
                     Resized(physical_size) => {
                         resized = Some(physical_size);
@@ -115,6 +387,7 @@ impl Controller {
                 match event {
                     DeviceEvent::MouseMotion { delta } => {
                         self.scene
+                            .borrow()
                             .camera
                             .borrow_mut()
                             .local_rotate([delta.0 as f32, delta.1 as f32]);
@@ -126,30 +399,38 @@ impl Controller {
             if fixed.should_render() {
                 fixed.refresh();
 
+                for error in self.streamer.update(&mut self.scene.borrow_mut()) {
+                    eprintln!("{error}");
+                }
+                for recovered in self.streamer.take_recovered() {
+                    eprintln!("{recovered}");
+                }
+
                 if input.is_pressed(KeyCode::KeyQ) {
-                    self.scene.camera.borrow_mut().local_roll(1.5);
+                    self.scene.borrow().camera.borrow_mut().local_roll(1.5);
                 }
                 if input.is_pressed(KeyCode::KeyE) {
-                    self.scene.camera.borrow_mut().local_roll(-1.5);
+                    self.scene.borrow().camera.borrow_mut().local_roll(-1.5);
                 }
 
                 if input.is_pressed(KeyCode::KeyW) {
-                    self.scene.camera.borrow_mut().local_move([0.0, 0.5, 0.0]);
+                    self.scene.borrow().camera.borrow_mut().local_move([0.0, 0.5, 0.0]);
                 }
                 if input.is_pressed(KeyCode::KeyS) {
-                    self.scene.camera.borrow_mut().local_move([0.0, -0.5, 0.0]);
+                    self.scene.borrow().camera.borrow_mut().local_move([0.0, -0.5, 0.0]);
                 }
                 if input.is_pressed(KeyCode::KeyA) {
-                    self.scene.camera.borrow_mut().local_move([-0.5, 0.0, 0.0]);
+                    self.scene.borrow().camera.borrow_mut().local_move([-0.5, 0.0, 0.0]);
                 }
                 if input.is_pressed(KeyCode::KeyD) {
-                    self.scene.camera.borrow_mut().local_move([0.5, 0.0, 0.0]);
+                    self.scene.borrow().camera.borrow_mut().local_move([0.5, 0.0, 0.0]);
                 }
                 if input.is_pressed(KeyCode::Space) {
-                    self.scene.camera.borrow_mut().local_move([0.0, 0.0, 0.5]);
+                    self.scene.borrow().camera.borrow_mut().local_move([0.0, 0.0, 0.5]);
                 }
-                if input.is_pressed(KeyCode::ControlLeft) {
-                    self.scene.camera.borrow_mut().local_move([0.0, 0.0, -0.5]);
+                // control is the modifier of the undo and clipboard keys
+                if input.is_pressed(KeyCode::ShiftLeft) {
+                    self.scene.borrow().camera.borrow_mut().local_move([0.0, 0.0, -0.5]);
                 }
                 if input.is_pressed(KeyCode::Minus) {
                     self.render_controller.fov_minus();
@@ -161,6 +442,9 @@ impl Controller {
                 if input.is_pressed(KeyCode::Escape) {
                     break 'main;
                 }
+
+                let target = self.target().map(|hit| hit.pos);
+                self.render_controller.set_target(target);
             }
 
             if let Some(physical_size) = resized {
@@ -180,5 +464,8 @@ impl Controller {
                 );
             }
         }
+        if let Err(error) = self.streamer.save_modified(&mut self.scene.borrow_mut()) {
+            eprintln!("Failed to save chunks, {error}");
+        }
     }
 }
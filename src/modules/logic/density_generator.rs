@@ -0,0 +1,296 @@
+use crate::modules::math::noise::{Fractal, Noise};
+
+use super::{
+    biome::{Biome, BiomeMap},
+    block_registry::BlockRegistry,
+    chunk::Chunk,
+    scene::ChunkIndex,
+    world_generator::{Surface, TerrainBlocks, WorldGenerator},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensitySettings {
+    /// World z where the terrain is half solid
+    pub base_height: f32,
+    /// Distance from `base_height` over which noise can still turn air solid and
+    /// solid to air, larger values give higher cliffs and overhangs
+    pub falloff: f32,
+    pub fractal: Fractal,
+    /// Used when there are no biomes
+    pub dirt_depth: isize,
+    pub caves: Option<CaveSettings>,
+}
+
+/// Tunnels run where the zero surfaces of two noise fields intersect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaveSettings {
+    pub frequency: f32,
+    /// In noise units, larger values widen the tunnels
+    pub radius: f32,
+}
+
+impl Default for DensitySettings {
+    fn default() -> Self {
+        Self {
+            base_height: 0.0,
+            falloff: 64.0,
+            fractal: Fractal {
+                frequency: 1.0 / 48.0,
+                ..Default::default()
+            },
+            dirt_depth: 3,
+            caves: Some(CaveSettings {
+                frequency: 1.0 / 48.0,
+                radius: 0.08,
+            }),
+        }
+    }
+}
+
+/// Terrain from a 3D density field, solid where it is positive
+pub struct DensityGenerator {
+    terrain: Noise,
+    worm_a: Noise,
+    worm_b: Noise,
+    settings: DensitySettings,
+    biomes: BiomeMap,
+}
+
+impl DensityGenerator {
+    pub fn new(seed: u64, settings: DensitySettings, registry: &mut BlockRegistry) -> Self {
+        let blocks = TerrainBlocks::register(registry, settings.dirt_depth);
+        Self::with_biomes(seed, settings, vec![Biome::uniform(blocks)])
+    }
+
+    pub fn with_biomes(seed: u64, settings: DensitySettings, biomes: Vec<Biome>) -> Self {
+        let noise = Noise::new(seed);
+        Self {
+            terrain: noise.derive(0),
+            worm_a: noise.derive(1),
+            worm_b: noise.derive(2),
+            settings,
+            biomes: BiomeMap::new(seed, biomes),
+        }
+    }
+
+    pub fn biomes(&self) -> &BiomeMap {
+        &self.biomes
+    }
+
+    /// Noise pushed towards solid below `base_height` and towards air above
+    pub fn density(&self, pos: [isize; 3]) -> f32 {
+        let height_scale = self.biomes.sample([pos[0], pos[1]]).height_scale;
+        self.column_density(pos, height_scale)
+    }
+
+    fn column_density(&self, pos: [isize; 3], height_scale: f32) -> f32 {
+        let DensitySettings {
+            base_height,
+            falloff,
+            fractal,
+            ..
+        } = self.settings;
+        let gradient = (base_height - pos[2] as f32) / (falloff * height_scale);
+        self.terrain.fractal3(pos.map(|c| c as f32), fractal) + gradient
+    }
+
+    pub fn is_cave(&self, pos: [isize; 3]) -> bool {
+        let Some(CaveSettings { frequency, radius }) = self.settings.caves else {
+            return false;
+        };
+        let pos = pos.map(|c| c as f32 * frequency);
+        // the z axis is squashed so tunnels run rather horizontally
+        let pos = [pos[0], pos[1], pos[2] * 2.0];
+        let a = self.worm_a.perlin3(pos);
+        let b = self.worm_b.perlin3(pos);
+        a * a + b * b < radius * radius
+    }
+
+    /// Z range that can contain solid voxels
+    fn solid_range(&self) -> (isize, isize) {
+        // the fractal stays in -1..=1, so the gradient alone decides out there
+        let max_scale = self
+            .biomes
+            .biomes()
+            .iter()
+            .map(|biome| biome.height_scale)
+            .fold(0.0, f32::max);
+        let DensitySettings {
+            base_height,
+            falloff,
+            ..
+        } = self.settings;
+        (
+            (base_height - falloff * max_scale).floor() as isize,
+            (base_height + falloff * max_scale).ceil() as isize,
+        )
+    }
+}
+
+impl Surface for DensityGenerator {
+    fn surface(&self, column: [isize; 2]) -> Option<isize> {
+        let height_scale = self.biomes.sample(column).height_scale;
+        let (lowest, highest) = self.solid_range();
+        let top = (lowest..=highest)
+            .rev()
+            .find(|&z| self.column_density([column[0], column[1], z], height_scale) > 0.0)?;
+        (!self.is_cave([column[0], column[1], top])).then_some(top)
+    }
+
+    fn decoration_density(&self, column: [isize; 2]) -> f32 {
+        self.biomes.sample(column).biome.decoration_density
+    }
+}
+
+impl WorldGenerator for DensityGenerator {
+    fn generate(&self, idx: ChunkIndex) -> Option<Chunk> {
+        const D: isize = Chunk::DIMENSIONS as isize;
+        let origin = idx.map(|c| c * D);
+        if origin[2] > self.solid_range().1 {
+            return None;
+        }
+
+        let mut chunk = Chunk::empty();
+        for y in 0..D {
+            for x in 0..D {
+                let column = [origin[0] + x, origin[1] + y];
+                let sample = self.biomes.sample(column);
+                let blocks = sample.biome.blocks;
+                // solid voxels above, counted from the voxels above the chunk
+                let mut depth = 0;
+                for z in (0..D + blocks.filler_depth + 1).rev() {
+                    let pos = [column[0], column[1], origin[2] + z];
+                    if self.column_density(pos, sample.height_scale) <= 0.0 {
+                        depth = 0;
+                        continue;
+                    }
+                    if z < D && !self.is_cave(pos) {
+                        let voxel = blocks.layer(depth);
+                        chunk.set([x, y, z].map(|c| c as usize), voxel);
+                    }
+                    depth += 1;
+                }
+            }
+        }
+        (!chunk.is_empty()).then_some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod density_generator_tests {
+    use crate::modules::logic::{
+        biome::{parse_biomes, DEFAULT_BIOMES},
+        block_registry::BlockRegistry,
+        chunk::Chunk,
+        scene::{ChunkIndex, Scene},
+        test_utils::{for_each_local, hash_chunk},
+        world_generator::WorldGenerator,
+    };
+
+    use super::{DensityGenerator, DensitySettings};
+
+    const D: usize = Chunk::DIMENSIONS;
+
+    fn generator(seed: u64, settings: DensitySettings) -> DensityGenerator {
+        DensityGenerator::new(seed, settings, &mut BlockRegistry::default())
+    }
+
+    fn solid_count(chunk: &Option<Chunk>) -> usize {
+        let Some(chunk) = chunk else {
+            return 0;
+        };
+        let mut count = 0;
+        for_each_local(|pos| count += (chunk.get(pos) != 0) as usize);
+        count
+    }
+
+    const SAMPLES: [ChunkIndex; 4] = [[0, 0, 0], [0, 0, -1], [-3, 5, 0], [7, -2, -2]];
+
+    #[test]
+    fn test_regression() {
+        let generator = generator(1234, DensitySettings::default());
+        let hashes = SAMPLES.map(|idx| hash_chunk(generator.generate(idx).as_ref()));
+        let expected = [
+            0x0df0_6d38_0b60_7116,
+            0x91dd_496a_adfd_e157,
+            0xee89_198a_a108_8a9f,
+            0x05b6_62d3_70e9_0406,
+        ];
+        assert_eq!(expected, hashes);
+    }
+
+    #[test]
+    fn test_seed() {
+        let hashes = |seed| {
+            let generator = generator(seed, DensitySettings::default());
+            SAMPLES.map(|idx| hash_chunk(generator.generate(idx).as_ref()))
+        };
+        assert_eq!(hashes(5), hashes(5));
+        assert_ne!(hashes(5), hashes(6));
+    }
+
+    #[test]
+    fn test_overhangs() {
+        let generator = generator(3, DensitySettings::default());
+        assert!(generator.generate([0, 0, 2]).is_none());
+
+        // some column has air below a solid voxel, excluding caves
+        let mut overhangs = 0;
+        for idx in [[0, 0, -1], [0, 0, 0], [0, 0, 1], [1, 0, 0], [1, 0, 1]] {
+            let Some(chunk) = generator.generate(idx) else {
+                continue;
+            };
+            for_each_local(|[x, y, z]| {
+                let world = [
+                    idx[0] * D as isize + x as isize,
+                    idx[1] * D as isize + y as isize,
+                    idx[2] * D as isize + z as isize,
+                ];
+                if z > 0 && chunk.get([x, y, z]) != 0 && chunk.get([x, y, z - 1]) == 0 {
+                    let below = [world[0], world[1], world[2] - 1];
+                    overhangs += !generator.is_cave(below) as usize;
+                }
+            });
+        }
+        assert!(overhangs > 0);
+    }
+
+    #[test]
+    fn test_caves() {
+        let solid = generator(
+            8,
+            DensitySettings {
+                caves: None,
+                ..Default::default()
+            },
+        );
+        let carved = generator(8, DensitySettings::default());
+        let idx = [0, 0, -3];
+        let full = solid_count(&solid.generate(idx));
+        let caves = solid_count(&carved.generate(idx));
+        assert_eq!(D * D * D, full);
+        assert!(caves < full);
+    }
+
+    #[test]
+    fn test_biome_surface() {
+        let mut registry = BlockRegistry::default();
+        let biomes = parse_biomes(DEFAULT_BIOMES, &mut registry).unwrap();
+        let settings = DensitySettings {
+            caves: None,
+            ..Default::default()
+        };
+        let generator = DensityGenerator::with_biomes(4, settings, biomes);
+
+        for column in [[0, 0], [3000, -200], [-900, 4100]] {
+            let top = (-200..200)
+                .rev()
+                .find(|&z| generator.density([column[0], column[1], z]) > 0.0)
+                .unwrap();
+            let (idx, local) = Scene::locate([column[0], column[1], top]);
+            let chunk = generator.generate(idx).unwrap();
+            let biome = generator.biomes().sample(column).biome;
+            assert_eq!(biome.blocks.surface, chunk.get(local), "{}", biome.name);
+        }
+    }
+}
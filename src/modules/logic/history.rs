@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use super::{scene::WorldPos, voxel::Voxel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxelEdit {
+    pub pos: WorldPos,
+    pub before: Voxel,
+    pub after: Voxel,
+}
+
+/// Edits undone and redone together, e.g. a whole brush stroke
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// In the order they were made
+    pub edits: Vec<VoxelEdit>,
+}
+
+impl Transaction {
+    pub fn size_in_bytes(&self) -> usize {
+        self.edits.len() * size_of::<VoxelEdit>()
+    }
+}
+
+/// Undo and redo stacks of the voxel edits of a `Scene`.
+/// Only edits made while a transaction is open are recorded
+pub struct History {
+    undo: VecDeque<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+    /// Oldest transactions are dropped once both stacks take more
+    memory_limit: usize,
+    memory_usage: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MEMORY_LIMIT)
+    }
+}
+
+impl History {
+    pub const DEFAULT_MEMORY_LIMIT: usize = 64 << 20;
+
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            memory_limit,
+            memory_usage: 0,
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+        self.trim();
+    }
+
+    /// Starts recording, an already open transaction is continued
+    pub fn begin(&mut self) {
+        self.open.get_or_insert_with(Transaction::default);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.open.is_some()
+    }
+
+    pub fn record(&mut self, edit: VoxelEdit) {
+        if let Some(open) = &mut self.open {
+            open.edits.push(edit);
+        }
+    }
+
+    /// Closes the open transaction, new edits make the undone ones unreachable
+    pub fn commit(&mut self) {
+        let Some(transaction) = self.open.take() else {
+            return;
+        };
+        if transaction.edits.is_empty() {
+            return;
+        }
+        for undone in self.redo.drain(..) {
+            self.memory_usage -= undone.size_in_bytes();
+        }
+        self.memory_usage += transaction.size_in_bytes();
+        self.undo.push_back(transaction);
+        self.trim();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.open.as_ref().is_some_and(|t| !t.edits.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Latest transaction to revert, commits the open one first.
+    /// Hand it back with `undone` once reverted
+    pub fn take_undo(&mut self) -> Option<Transaction> {
+        self.commit();
+        let transaction = self.undo.pop_back()?;
+        self.memory_usage -= transaction.size_in_bytes();
+        Some(transaction)
+    }
+
+    pub fn undone(&mut self, transaction: Transaction) {
+        self.memory_usage += transaction.size_in_bytes();
+        self.redo.push(transaction);
+    }
+
+    /// Latest undone transaction to apply again, hand it back with `redone`
+    pub fn take_redo(&mut self) -> Option<Transaction> {
+        self.commit();
+        let transaction = self.redo.pop()?;
+        self.memory_usage -= transaction.size_in_bytes();
+        Some(transaction)
+    }
+
+    pub fn redone(&mut self, transaction: Transaction) {
+        self.memory_usage += transaction.size_in_bytes();
+        self.undo.push_back(transaction);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.memory_usage > self.memory_limit {
+            let Some(oldest) = self.undo.pop_front() else {
+                break;
+            };
+            self.memory_usage -= oldest.size_in_bytes();
+        }
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use crate::modules::logic::{
+        block_registry::BlockRegistry,
+        brush::{self, Shape},
+        scene::Scene,
+        voxel::{Voxel, AIR},
+    };
+
+    use super::{History, VoxelEdit};
+
+    const STONE: Voxel = 1;
+
+    fn scene() -> Scene {
+        let mut registry = BlockRegistry::default();
+        registry.register_color([128, 128, 128, 255]);
+        Scene::new(registry)
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut scene = scene();
+        scene.set_voxel([0, 0, 0], STONE);
+        // outside of a transaction
+        assert!(!scene.undo());
+
+        let stroke = Shape::Box {
+            a: [-1, 0, 0],
+            b: [1, 0, 0],
+        };
+        scene.begin_transaction();
+        brush::fill(&mut scene, &stroke, STONE);
+        scene.set_voxel([0, 0, 0], AIR);
+        scene.commit_transaction();
+
+        assert!(scene.undo());
+        assert_eq!(AIR, scene.get_voxel([-1, 0, 0]));
+        assert_eq!(STONE, scene.get_voxel([0, 0, 0]));
+        assert!(!scene.undo());
+
+        assert!(scene.redo());
+        assert_eq!(STONE, scene.get_voxel([-1, 0, 0]));
+        assert_eq!(AIR, scene.get_voxel([0, 0, 0]));
+        assert!(!scene.redo());
+
+        // a new edit drops the undone ones
+        scene.undo();
+        scene.begin_transaction();
+        scene.set_voxel([5, 5, 5], STONE);
+        scene.commit_transaction();
+        assert!(!scene.redo());
+        assert!(scene.undo());
+        assert_eq!(AIR, scene.get_voxel([5, 5, 5]));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let edit = VoxelEdit {
+            pos: [0; 3],
+            before: AIR,
+            after: STONE,
+        };
+        let size = size_of::<VoxelEdit>();
+        let mut history = History::new(5 * size);
+        for edits in [2, 2, 2] {
+            history.begin();
+            for _ in 0..edits {
+                history.record(edit);
+            }
+            history.commit();
+        }
+        // the first transaction was dropped
+        assert_eq!(4 * size, history.memory_usage());
+        assert!(history.take_undo().is_some());
+        assert!(history.take_undo().is_some());
+        assert!(history.take_undo().is_none());
+
+        history.begin();
+        history.commit();
+        assert_eq!(0, history.memory_usage());
+        history.set_memory_limit(0);
+        history.begin();
+        history.record(edit);
+        history.commit();
+        assert!(!history.can_undo());
+    }
+}
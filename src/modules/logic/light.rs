@@ -4,6 +4,8 @@ pub struct Light {
     pub direction: Vec3,
     pub color: [f32; 3],
     pub intensity: f32,
+    /// Light received by faces turned away
+    pub ambient: f32,
 }
 
 impl Default for Light {
@@ -12,6 +14,7 @@ impl Default for Light {
             direction: [0.0, -1.0, 0.0],
             color: [1.0, 1.0, 1.0],
             intensity: 1.0,
+            ambient: 0.3,
         }
     }
 }
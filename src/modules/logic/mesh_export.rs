@@ -0,0 +1,329 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+use super::{
+    block_registry::BlockRegistry,
+    chunk::Chunk,
+    chunk_mesher::{self, ChunkMesh, MeshSettings},
+    scene::{ChunkIndex, Scene},
+    voxel::Color,
+};
+
+/// Chunk meshes merged in world space, converted to the y up convention of
+/// OBJ and glTF. Needs no GPU, so it also works headless
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExportMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Block color of every vertex
+    pub colors: Vec<Color>,
+    /// Ambient occlusion as a brightness factor, like `voxel_vertex.vert` applies it
+    pub shades: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+/// Engine z up to y up, keeping the handedness
+fn y_up([x, y, z]: [f32; 3]) -> [f32; 3] {
+    // subtracting keeps -0 out of the files
+    [x, z, 0.0 - y]
+}
+
+impl ExportMesh {
+    /// Every chunk of the scene
+    pub fn from_scene(scene: &Scene, settings: MeshSettings) -> Self {
+        let mut chunks: Vec<_> = scene.get_chunks().map(|(idx, _)| *idx).collect();
+        chunks.sort();
+        Self::from_chunks(scene, chunks, settings)
+    }
+
+    /// Meshes the chunks with their loaded neighbours, so faces between them are culled
+    pub fn from_chunks(
+        scene: &Scene,
+        chunks: impl IntoIterator<Item = ChunkIndex>,
+        settings: MeshSettings,
+    ) -> Self {
+        let mut export = Self::default();
+        for idx in chunks {
+            let Some(chunk) = scene.get_chunk(idx) else {
+                continue;
+            };
+            let neighbours = scene.neighbours(idx);
+            let mesh =
+                chunk_mesher::mesh_with_neighbours(chunk, &neighbours, scene.registry(), settings);
+            export.push_chunk(idx, &mesh, scene.registry());
+        }
+        export
+    }
+
+    pub fn push_chunk(&mut self, idx: ChunkIndex, mesh: &ChunkMesh, registry: &BlockRegistry) {
+        const D: isize = Chunk::DIMENSIONS as isize;
+        let base = self.positions.len() as u32;
+        for vertex in &mesh.vertices {
+            let pos = vertex.pos();
+            let world = [0, 1, 2].map(|i| (idx[i] * D) as f32 + pos[i] as f32);
+            self.positions.push(y_up(world));
+            self.normals.push(y_up(vertex.normal().map(|c| c as f32)));
+            self.colors.push(registry[vertex.block()].color);
+            self.shades.push(0.4 + 0.6 * vertex.ao() as f32 / 3.0);
+        }
+        self.indices
+            .extend(chunk_mesher::quad_indices(mesh.quad_count()).map(|index| base + index));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Shaded vertex color in 0..=1
+    fn shaded(&self, vertex: usize) -> [f32; 4] {
+        let [r, g, b, a] = self.colors[vertex].map(|c| c as f32 / 255.0);
+        let shade = self.shades[vertex];
+        [r * shade, g * shade, b * shade, a]
+    }
+
+    /// Writes vertex colors as the widespread `v x y z r g b` extension and
+    /// one material per block color for tools that ignore them.
+    /// `mtl_name` is the file name the OBJ refers to
+    pub fn write_obj(
+        &self,
+        obj: &mut impl Write,
+        mtl: &mut impl Write,
+        mtl_name: &str,
+    ) -> io::Result<()> {
+        writeln!(obj, "mtllib {mtl_name}")?;
+        for (vertex, [x, y, z]) in self.positions.iter().enumerate() {
+            let [r, g, b, _] = self.shaded(vertex);
+            writeln!(obj, "v {x} {y} {z} {r:.4} {g:.4} {b:.4}")?;
+        }
+        for [x, y, z] in &self.normals {
+            writeln!(obj, "vn {x} {y} {z}")?;
+        }
+
+        // triangles grouped by the color of their first vertex
+        let mut materials: BTreeMap<Color, Vec<&[u32]>> = BTreeMap::new();
+        for triangle in self.indices.chunks_exact(3) {
+            let color = self.colors[triangle[0] as usize];
+            materials.entry(color).or_default().push(triangle);
+        }
+        for (color, triangles) in &materials {
+            let name = material_name(*color);
+            let [r, g, b, a] = color.map(|c| c as f32 / 255.0);
+            writeln!(mtl, "newmtl {name}\nKd {r:.4} {g:.4} {b:.4}\nd {a:.4}\n")?;
+            writeln!(obj, "usemtl {name}")?;
+            for triangle in triangles {
+                let [a, b, c] = [0, 1, 2].map(|i| triangle[i] + 1);
+                writeln!(obj, "f {a}//{a} {b}//{b} {c}//{c}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Binary glTF 2.0 with positions, normals, linear vertex colors and indices
+    pub fn to_glb(&self) -> Vec<u8> {
+        let mut bin = Vec::new();
+        let mut views = Vec::new();
+        let mut view = |bin: &mut Vec<u8>, data: &[u8], target: u32| {
+            views.push(format!(
+                r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{target}}}"#,
+                bin.len(),
+                data.len()
+            ));
+            bin.extend_from_slice(data);
+        };
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+        view(
+            &mut bin,
+            &floats(self.positions.iter().flatten()),
+            ARRAY_BUFFER,
+        );
+        view(
+            &mut bin,
+            &floats(self.normals.iter().flatten()),
+            ARRAY_BUFFER,
+        );
+        let colors: Vec<_> = (0..self.colors.len())
+            .flat_map(|vertex| {
+                let [r, g, b, a] = self.shaded(vertex);
+                [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+            })
+            .collect();
+        view(&mut bin, &floats(&colors), ARRAY_BUFFER);
+        let indices: Vec<u8> = self.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        view(&mut bin, &indices, ELEMENT_ARRAY_BUFFER);
+
+        let mut json = String::from(r#"{"asset":{"version":"2.0","generator":"voxel_engine"},"#);
+        json.push_str(r#""scene":0,"scenes":[{"nodes":[0]}],"#);
+        if self.is_empty() {
+            // accessors may not be empty
+            json.push_str(r#""nodes":[{}]}"#);
+            return glb(json, Vec::new());
+        }
+
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        let count = self.positions.len();
+        let [min, max] = [f32::min, f32::max].map(|pick| {
+            let bound = self.positions.iter().fold(self.positions[0], |bound, pos| {
+                [0, 1, 2].map(|i| pick(bound[i], pos[i]))
+            });
+            format!("[{},{},{}]", bound[0], bound[1], bound[2])
+        });
+        let translucent = self.colors.iter().any(|color| color[3] < 255);
+        write!(
+            json,
+            concat!(
+                r#""nodes":[{{"mesh":0}}],"#,
+                r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"indices":3,"material":0}}]}}],"#,
+                r#""materials":[{{"pbrMetallicRoughness":{{"metallicFactor":0,"roughnessFactor":1}},"alphaMode":"{alpha}"}}],"#,
+                r#""accessors":["#,
+                r#"{{"bufferView":0,"componentType":{FLOAT},"count":{count},"type":"VEC3","min":{min},"max":{max}}},"#,
+                r#"{{"bufferView":1,"componentType":{FLOAT},"count":{count},"type":"VEC3"}},"#,
+                r#"{{"bufferView":2,"componentType":{FLOAT},"count":{count},"type":"VEC4"}},"#,
+                r#"{{"bufferView":3,"componentType":{UNSIGNED_INT},"count":{indices},"type":"SCALAR"}}],"#,
+                r#""bufferViews":[{views}],"buffers":[{{"byteLength":{length}}}]}}"#,
+            ),
+            alpha = if translucent { "BLEND" } else { "OPAQUE" },
+            FLOAT = FLOAT,
+            UNSIGNED_INT = UNSIGNED_INT,
+            count = count,
+            min = min,
+            max = max,
+            indices = self.indices.len(),
+            views = views.join(","),
+            length = bin.len(),
+        )
+        .unwrap();
+        glb(json, bin)
+    }
+}
+
+fn floats<'a>(values: impl IntoIterator<Item = &'a f32>) -> Vec<u8> {
+    values.into_iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn material_name([r, g, b, a]: Color) -> String {
+    format!("color_{r:02x}{g:02x}{b:02x}{a:02x}")
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Header, JSON chunk and an optional BIN chunk, both padded to 4 bytes
+fn glb(json: String, mut bin: Vec<u8>) -> Vec<u8> {
+    let mut json = json.into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+    let mut chunks = Vec::new();
+    for (kind, data) in [(b"JSON", &json), (b"BIN\0", &bin)] {
+        if data.is_empty() {
+            continue;
+        }
+        chunks.extend((data.len() as u32).to_le_bytes());
+        chunks.extend(kind);
+        chunks.extend(data.iter());
+    }
+
+    let mut bytes = b"glTF".to_vec();
+    bytes.extend(2u32.to_le_bytes());
+    bytes.extend((12 + chunks.len() as u32).to_le_bytes());
+    bytes.extend(chunks);
+    bytes
+}
+
+#[cfg(test)]
+mod mesh_export_tests {
+    use crate::modules::logic::{
+        block_registry::BlockRegistry, chunk_mesher::MeshSettings, scene::Scene,
+    };
+
+    use super::ExportMesh;
+
+    fn scene(voxels: &[[isize; 3]]) -> Scene {
+        let mut registry = BlockRegistry::default();
+        let red = registry.register_color([255, 0, 0, 255]);
+        let mut scene = Scene::new(registry);
+        for pos in voxels {
+            scene.set_voxel(*pos, red);
+        }
+        scene
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn test_world_space() {
+        // a voxel on each side of the border between chunk x -1 and 0
+        let mesh =
+            ExportMesh::from_scene(&scene(&[[-1, 2, 3], [0, 2, 3]]), MeshSettings::default());
+        // the faces between both are culled
+        assert_eq!(10 * 6, mesh.indices.len());
+        for ([x, y, z], normal) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!((-1.0..=1.0).contains(x));
+            // z up turned to y up
+            assert!((3.0..=4.0).contains(y));
+            assert!((-3.0..=-2.0).contains(z));
+            assert_eq!(1.0, normal.iter().map(|c| c.abs()).sum::<f32>());
+        }
+
+        let region = ExportMesh::from_chunks(
+            &scene(&[[0, 0, 0], [40, 0, 0]]),
+            [[1, 0, 0]],
+            MeshSettings::default(),
+        );
+        assert_eq!(6 * 6, region.indices.len());
+        assert!(region.positions.iter().all(|pos| pos[0] >= 40.0));
+    }
+
+    #[test]
+    fn test_obj() {
+        let mesh = ExportMesh::from_scene(&scene(&[[0, 0, 0]]), MeshSettings::default());
+        let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+        mesh.write_obj(&mut obj, &mut mtl, "test.mtl").unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(24, count("v "));
+        assert_eq!(24, count("vn "));
+        assert_eq!(12, count("f "));
+        assert!(obj.contains("v 0 0 0 1.0000 0.0000 0.0000"));
+        assert!(obj.contains("usemtl color_ff0000ff"));
+        assert!(mtl.contains("newmtl color_ff0000ff\nKd 1.0000 0.0000 0.0000"));
+    }
+
+    #[test]
+    fn test_glb() {
+        let mesh = ExportMesh::from_scene(&scene(&[[0, 0, 0]]), MeshSettings::default());
+        let glb = mesh.to_glb();
+        assert_eq!(b"glTF", &glb[..4]);
+        assert_eq!(2, u32_at(&glb, 4));
+        assert_eq!(glb.len(), u32_at(&glb, 8));
+
+        let json_len = u32_at(&glb, 12);
+        assert_eq!(b"JSON", &glb[16..20]);
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        assert!(json.contains(r#""count":24,"type":"VEC3","min":[0,0,-1],"max":[1,1,0]"#));
+        assert!(json.contains(r#""count":36,"type":"SCALAR""#));
+
+        let bin = 20 + json_len;
+        // positions, normals, colors and indices
+        let bin_len = 24 * 12 + 24 * 12 + 24 * 16 + 36 * 4;
+        assert_eq!(bin_len, u32_at(&glb, bin));
+        assert_eq!(b"BIN\0", &glb[bin + 4..bin + 8]);
+        assert_eq!(glb.len(), bin + 8 + bin_len);
+
+        let empty = ExportMesh::default().to_glb();
+        assert_eq!(empty.len(), u32_at(&empty, 8));
+    }
+}
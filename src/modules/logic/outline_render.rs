@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use vulkano::{
+    buffer::BufferContents,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::{CullMode, PolygonMode, RasterizationState},
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        layout::{PipelineLayoutCreateInfo, PushConstantRange},
+        DynamicState, PipelineShaderStageCreateInfo,
+    },
+    render_pass::Subpass,
+    shader::ShaderStages,
+};
+
+use crate::modules::{math::mat::Mat4x4, renderer::Renderer, shaders};
+
+#[derive(BufferContents, Vertex, Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct OutlineVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub pos: [f32; 3],
+}
+
+/// Line list of the 12 edges of a voxel, grown a bit so the lines are not
+/// hidden by the voxel faces
+pub fn voxel_outline() -> Vec<OutlineVertex> {
+    const GROW: f32 = 0.005;
+    let corner = |bits: usize| OutlineVertex {
+        pos: [0, 1, 2].map(|axis| {
+            if bits >> axis & 1 == 1 {
+                1.0 + GROW
+            } else {
+                -GROW
+            }
+        }),
+    };
+    let mut vertices = Vec::new();
+    for bits in 0..8 {
+        for axis in 0..3 {
+            // every edge once, from the corner with the lower coordinate
+            if bits >> axis & 1 == 0 {
+                vertices.push(corner(bits));
+                vertices.push(corner(bits | 1 << axis));
+            }
+        }
+    }
+    vertices
+}
+
+pub fn outline_graphics_pipeline(
+    renderer: &Renderer,
+    subpass: Subpass,
+) -> GraphicsPipelineCreateInfo {
+    let vertex_shader = renderer.load_shader(shaders::outline_vertex_shader::load);
+    let fragment_shader = renderer.load_shader(shaders::default_fragment_shader::load);
+
+    let vertex_input_state = OutlineVertex::per_vertex()
+        .definition(&vertex_shader.info().input_interface)
+        .unwrap();
+
+    let pipeline_stages = vec![
+        PipelineShaderStageCreateInfo::new(vertex_shader),
+        PipelineShaderStageCreateInfo::new(fragment_shader),
+    ];
+
+    let rasterization_state = RasterizationState {
+        polygon_mode: PolygonMode::Fill,
+        cull_mode: CullMode::None,
+        ..Default::default()
+    };
+
+    let input_assembly_state = InputAssemblyState {
+        topology: PrimitiveTopology::LineList,
+        ..Default::default()
+    };
+
+    let color_blend_state = ColorBlendState::with_attachment_states(
+        subpass.num_color_attachments(),
+        ColorBlendAttachmentState::default(),
+    );
+
+    // tested against the chunks, but does not hide anything itself
+    let depth_stencil_state = DepthStencilState {
+        depth: Some(DepthState {
+            write_enable: false,
+            compare_op: CompareOp::LessOrEqual,
+        }),
+        ..Default::default()
+    };
+
+    let layout = {
+        let push_constant_ranges = vec![PushConstantRange {
+            stages: ShaderStages::VERTEX,
+            size: size_of::<OutlinePushConstant>() as u32,
+            ..Default::default()
+        }];
+        let create_info = PipelineLayoutCreateInfo {
+            set_layouts: Default::default(),
+            push_constant_ranges,
+            ..Default::default()
+        };
+        renderer.pipeline_layout(create_info)
+    };
+
+    GraphicsPipelineCreateInfo {
+        stages: pipeline_stages.into(),
+        vertex_input_state: Some(vertex_input_state),
+        input_assembly_state: Some(input_assembly_state),
+        rasterization_state: Some(rasterization_state),
+        viewport_state: Some(ViewportState::default()),
+        multisample_state: Some(MultisampleState::default()),
+        color_blend_state: Some(color_blend_state),
+        depth_stencil_state: Some(depth_stencil_state),
+        subpass: Some(subpass.into()),
+        dynamic_state: HashSet::from_iter([DynamicState::Viewport].into_iter()),
+        ..GraphicsPipelineCreateInfo::layout(layout)
+    }
+}
+
+#[derive(BufferContents)]
+#[repr(C)]
+pub struct OutlinePushConstant {
+    pub pvm: Mat4x4,
+    pub color: [f32; 4],
+}
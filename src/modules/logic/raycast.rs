@@ -0,0 +1,153 @@
+use crate::modules::math::vec::{Vec3, VecNorm};
+
+use super::{
+    scene::{ChunkIndex, Scene, WorldPos},
+    voxel::AIR,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub pos: WorldPos,
+    /// Outward normal of the face the ray entered through,
+    /// zero when the ray starts inside the voxel
+    pub normal: [isize; 3],
+    /// Along the ray from its origin to the entry point
+    pub distance: f32,
+    pub chunk: ChunkIndex,
+}
+
+impl RayHit {
+    /// Voxel in front of the hit face, where a new voxel would be placed
+    pub fn adjacent(&self) -> WorldPos {
+        [0, 1, 2].map(|i| self.pos[i] + self.normal[i])
+    }
+}
+
+/// First non air voxel along the ray, stepping voxel by voxel (Amanatides and Woo).
+/// Voxel `pos` fills the unit cube from `pos` to `pos + 1`
+pub fn raycast(scene: &Scene, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+    if direction == [0.0; 3] || !max_distance.is_finite() {
+        return None;
+    }
+    let direction = direction.norm();
+    let mut pos: WorldPos = origin.map(|c| c.floor() as isize);
+    let step = direction.map(|c| c.signum() as isize);
+    // distance along the ray to the next boundary on every axis and between two of them
+    let mut next = [0.0; 3];
+    let mut delta = [f32::INFINITY; 3];
+    for i in 0..3 {
+        if direction[i] == 0.0 {
+            next[i] = f32::INFINITY;
+            continue;
+        }
+        let boundary = if step[i] > 0 {
+            pos[i] as f32 + 1.0
+        } else {
+            pos[i] as f32
+        };
+        next[i] = (boundary - origin[i]) / direction[i];
+        delta[i] = 1.0 / direction[i].abs();
+    }
+
+    let mut normal = [0; 3];
+    let mut distance = 0.0;
+    loop {
+        if scene.get_voxel(pos) != AIR {
+            return Some(RayHit {
+                pos,
+                normal,
+                distance,
+                chunk: Scene::locate(pos).0,
+            });
+        }
+        // ties go to the lowest axis, so rays through edges are deterministic
+        let axis = (0..3).min_by(|a, b| next[*a].total_cmp(&next[*b])).unwrap();
+        distance = next[axis];
+        if distance > max_distance {
+            return None;
+        }
+        pos[axis] += step[axis];
+        next[axis] += delta[axis];
+        normal = [0; 3];
+        normal[axis] = -step[axis];
+    }
+}
+
+#[cfg(test)]
+mod raycast_tests {
+    use crate::modules::logic::{block_registry::BlockRegistry, scene::Scene};
+
+    use super::raycast;
+
+    fn scene(voxels: &[[isize; 3]]) -> Scene {
+        let mut registry = BlockRegistry::default();
+        let stone = registry.register_color([128, 128, 128, 255]);
+        let mut scene = Scene::new(registry);
+        for pos in voxels {
+            scene.set_voxel(*pos, stone);
+        }
+        scene
+    }
+
+    #[test]
+    fn test_axis_aligned() {
+        let scene = scene(&[[5, 0, 0], [0, 0, -40]]);
+        let hit = raycast(&scene, [0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 10.0).unwrap();
+        assert_eq!([5, 0, 0], hit.pos);
+        assert_eq!([-1, 0, 0], hit.normal);
+        assert_eq!(4.5, hit.distance);
+        assert_eq!([4, 0, 0], hit.adjacent());
+        assert_eq!(None, raycast(&scene, [0.5, 0.5, 0.5], [1.0, 0.0, 0.0], 4.0));
+
+        // down into chunk z -2
+        let hit = raycast(&scene, [0.5, 0.5, 0.5], [0.0, 0.0, -3.0], 50.0).unwrap();
+        assert_eq!([0, 0, -40], hit.pos);
+        assert_eq!([0, 0, 1], hit.normal);
+        assert_eq!(39.5, hit.distance);
+        assert_eq!([0, 0, -2], hit.chunk);
+    }
+
+    #[test]
+    fn test_diagonal() {
+        // crosses from chunk 0 into chunks with negative x and y
+        let target = [-35, -34, 2];
+        let scene = scene(&[target]);
+        let origin = [0.5, 1.5, 2.5];
+        let direction = [-35.0, -35.0, 0.0];
+        let hit = raycast(&scene, origin, direction, 100.0).unwrap();
+        assert_eq!(target, hit.pos);
+        assert_eq!([-2, -2, 0], hit.chunk);
+        assert!(hit.normal == [1, 0, 0] || hit.normal == [0, 1, 0]);
+        assert!((hit.distance - 34.5 * 2f32.sqrt()).abs() < 1e-3);
+
+        let hit = raycast(&scene, [2.5, 0.1, 1.3], [-1.0, -0.9, 0.03], 100.0).unwrap();
+        assert_eq!(target, hit.pos);
+    }
+
+    #[test]
+    fn test_boundary_grazing() {
+        // the ray runs along the plane y = 0, between rows y -1 and 0
+        let scene = scene(&[[-3, -1, 0], [-6, 0, 0]]);
+        let hit = raycast(&scene, [0.5, 0.0, 0.5], [-1.0, 0.0, 0.0], 20.0).unwrap();
+        assert_eq!([-6, 0, 0], hit.pos);
+        assert_eq!([1, 0, 0], hit.normal);
+
+        // exactly through the corner of the chunks around the origin
+        let scene = self::scene(&[[-2, -2, -2]]);
+        let hit = raycast(&scene, [1.0, 1.0, 1.0], [-1.0, -1.0, -1.0], 10.0).unwrap();
+        assert_eq!([-2, -2, -2], hit.pos);
+        assert_eq!([-1, -1, -1], hit.chunk);
+        assert!((hit.distance - 3f32.sqrt() * 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_start_inside() {
+        let scene = scene(&[[0, 0, 0]]);
+        let hit = raycast(&scene, [0.2, 0.3, 0.4], [0.0, 1.0, 0.0], 5.0).unwrap();
+        assert_eq!(
+            ([0, 0, 0], [0; 3], 0.0),
+            (hit.pos, hit.normal, hit.distance)
+        );
+        assert_eq!(None, raycast(&scene, [0.2, 0.3, 0.4], [0.0; 3], 5.0));
+    }
+}
@@ -0,0 +1,708 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use super::{
+    block_registry::{Block, BlockRegistry},
+    chunk::Chunk,
+    chunk_streamer::ChunkSource,
+    scene::ChunkIndex,
+    voxel::Voxel,
+    world_generator::WorldGenerator,
+};
+
+/// Chunks per axis of a region file
+pub const REGION_SIZE: isize = 8;
+const CELLS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"VXRG";
+pub const FORMAT_VERSION: u16 = 1;
+/// Magic, version, region size and a reserved byte
+const PREAMBLE_LEN: usize = 8;
+/// Offset, length and checksum of every cell
+const ENTRY_LEN: usize = 12;
+/// Preamble, offset table and the checksum of both
+const HEADER_LEN: usize = PREAMBLE_LEN + CELLS * ENTRY_LEN + 4;
+
+pub type RegionIndex = [isize; 3];
+
+#[derive(Debug)]
+pub enum RegionError {
+    Io(io::Error),
+    /// The file is not a region file of a known version or it is damaged
+    Corrupt(String),
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionError::Io(error) => write!(f, "region io error: {error}"),
+            RegionError::Corrupt(reason) => write!(f, "corrupt region file: {reason}"),
+        }
+    }
+}
+
+impl From<io::Error> for RegionError {
+    fn from(error: io::Error) -> Self {
+        RegionError::Io(error)
+    }
+}
+
+fn corrupt<T>(reason: impl Into<String>) -> Result<T, RegionError> {
+    Err(RegionError::Corrupt(reason.into()))
+}
+
+/// Damaged region file moved aside, saving went on with a fresh one
+#[derive(Debug)]
+pub struct RecoveredRegion {
+    pub region: RegionIndex,
+    pub moved_to: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for RecoveredRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "region {:?} was corrupt, {}, moved it to {}",
+            self.region,
+            self.reason,
+            self.moved_to.display()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Entry {
+    /// From the start of the file, 0 if the chunk was never saved
+    offset: u32,
+    length: u32,
+    checksum: u32,
+}
+
+/// Stores chunks in region files of `REGION_SIZE`³ chunks each.
+/// Region headers are read the first time one of their chunks is requested
+pub struct RegionStorage {
+    dir: PathBuf,
+    headers: HashMap<RegionIndex, Option<Vec<Entry>>>,
+    recovered: Vec<RecoveredRegion>,
+}
+
+impl RegionStorage {
+    /// Region files are kept in `dir`, it is created when saving
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            headers: HashMap::new(),
+            recovered: Vec::new(),
+        }
+    }
+
+    /// Region files moved aside since the last call
+    pub fn take_recovered(&mut self) -> Vec<RecoveredRegion> {
+        std::mem::take(&mut self.recovered)
+    }
+
+    /// Region of the chunk and the cell of the chunk inside of it
+    pub fn locate(idx: ChunkIndex) -> (RegionIndex, usize) {
+        let region = idx.map(|c| c.div_euclid(REGION_SIZE));
+        let [x, y, z] = idx.map(|c| c.rem_euclid(REGION_SIZE) as usize);
+        let size = REGION_SIZE as usize;
+        (region, (z * size + y) * size + x)
+    }
+
+    fn path(&self, [x, y, z]: RegionIndex) -> PathBuf {
+        self.dir.join(format!("r.{x}.{y}.{z}.region"))
+    }
+
+    /// `None` if the chunk was never saved.
+    /// Blocks missing in `registry` are registered
+    pub fn load_chunk(
+        &mut self,
+        idx: ChunkIndex,
+        registry: &mut BlockRegistry,
+    ) -> Result<Option<Chunk>, RegionError> {
+        let (region, cell) = Self::locate(idx);
+        let Some(entry) = self.header(region)?.map(|header| header[cell]) else {
+            return Ok(None);
+        };
+        if entry.offset == 0 {
+            return Ok(None);
+        }
+        let mut file = fs::File::open(self.path(region))?;
+        file.seek(SeekFrom::Start(entry.offset as u64))?;
+        let mut data = vec![0; entry.length as usize];
+        file.read_exact(&mut data)?;
+        if crc32(&data) != entry.checksum {
+            return corrupt(format!("checksum mismatch of chunk {idx:?}"));
+        }
+        decode_chunk(&data, registry).map(Some)
+    }
+
+    /// Rewrites every affected region file once
+    pub fn save_chunks<'a>(
+        &mut self,
+        chunks: impl IntoIterator<Item = (ChunkIndex, &'a Chunk)>,
+        registry: &BlockRegistry,
+    ) -> Result<(), RegionError> {
+        let mut regions: HashMap<RegionIndex, Vec<(usize, Vec<u8>)>> = HashMap::new();
+        for (idx, chunk) in chunks {
+            let (region, cell) = Self::locate(idx);
+            let data = encode_chunk(chunk, registry);
+            regions.entry(region).or_default().push((cell, data));
+        }
+        for (region, cells) in regions {
+            self.save_region(region, cells)?;
+        }
+        Ok(())
+    }
+
+    fn header(&mut self, region: RegionIndex) -> Result<Option<&Vec<Entry>>, RegionError> {
+        if !self.headers.contains_key(&region) {
+            let header = match fs::File::open(self.path(region)) {
+                Ok(mut file) => {
+                    let mut bytes = vec![0; HEADER_LEN];
+                    file.read_exact(&mut bytes)
+                        .or_else(|error| match error.kind() {
+                            io::ErrorKind::UnexpectedEof => corrupt("truncated header"),
+                            _ => Err(error.into()),
+                        })?;
+                    Some(decode_header(&bytes)?)
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+                Err(error) => return Err(error.into()),
+            };
+            self.headers.insert(region, header);
+        }
+        Ok(self.headers[&region].as_ref())
+    }
+
+    /// Chunks already saved in the region file, by cell
+    fn saved_chunks(&mut self, region: RegionIndex) -> Result<Vec<Option<Vec<u8>>>, RegionError> {
+        let mut data = vec![None; CELLS];
+        let Some(header) = self.header(region)?.cloned() else {
+            return Ok(data);
+        };
+        let bytes = fs::read(self.path(region))?;
+        for (cell, entry) in header.iter().enumerate() {
+            if entry.offset == 0 {
+                continue;
+            }
+            let range = entry.offset as usize..entry.offset as usize + entry.length as usize;
+            let Some(chunk) = bytes.get(range) else {
+                return corrupt("chunk beyond the end of the file");
+            };
+            data[cell] = Some(chunk.to_vec());
+        }
+        Ok(data)
+    }
+
+    fn save_region(
+        &mut self,
+        region: RegionIndex,
+        cells: Vec<(usize, Vec<u8>)>,
+    ) -> Result<(), RegionError> {
+        // chunks already in the file are copied over
+        let mut data = match self.saved_chunks(region) {
+            Ok(data) => data,
+            // kept aside for recovery, the edits must not be lost with it
+            Err(RegionError::Corrupt(reason)) => {
+                let path = self.path(region);
+                let aside = path.with_extension("region.corrupt");
+                fs::rename(&path, &aside)?;
+                self.headers.remove(&region);
+                self.recovered.push(RecoveredRegion {
+                    region,
+                    moved_to: aside,
+                    reason,
+                });
+                vec![None; CELLS]
+            }
+            Err(error) => return Err(error),
+        };
+        for (cell, chunk) in cells {
+            data[cell] = Some(chunk);
+        }
+
+        let mut header = vec![Entry::default(); CELLS];
+        let mut body = Vec::new();
+        for (cell, chunk) in data.iter().enumerate() {
+            let Some(chunk) = chunk else {
+                continue;
+            };
+            header[cell] = Entry {
+                offset: (HEADER_LEN + body.len()) as u32,
+                length: chunk.len() as u32,
+                checksum: crc32(chunk),
+            };
+            body.extend_from_slice(chunk);
+        }
+        let mut file = encode_header(&header);
+        file.extend(body);
+
+        fs::create_dir_all(&self.dir)?;
+        // a crash while writing must not destroy the previous file
+        let path = self.path(region);
+        let temporary = path.with_extension("region.tmp");
+        fs::write(&temporary, file)?;
+        fs::rename(temporary, path)?;
+        self.headers.insert(region, Some(header));
+        Ok(())
+    }
+}
+
+/// Loads saved chunks and generates the ones that never were
+pub struct PersistentWorld<G> {
+    pub storage: RegionStorage,
+    pub generator: G,
+}
+
+impl<G: WorldGenerator> ChunkSource for PersistentWorld<G> {
+    /// Unreadable chunks are not generated, a later save would overwrite them
+    fn load(
+        &mut self,
+        idx: ChunkIndex,
+        registry: &mut BlockRegistry,
+    ) -> Result<Option<Chunk>, RegionError> {
+        match self.storage.load_chunk(idx, registry)? {
+            Some(chunk) => Ok(Some(chunk)),
+            None => Ok(self.generator.generate(idx)),
+        }
+    }
+
+    fn save(
+        &mut self,
+        chunks: &[(ChunkIndex, &Chunk)],
+        registry: &BlockRegistry,
+    ) -> Result<(), RegionError> {
+        self.storage.save_chunks(chunks.iter().copied(), registry)
+    }
+
+    fn take_recovered(&mut self) -> Vec<RecoveredRegion> {
+        self.storage.take_recovered()
+    }
+}
+
+fn encode_header(header: &[Entry]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.push(REGION_SIZE as u8);
+    bytes.push(0);
+    for entry in header {
+        for value in [entry.offset, entry.length, entry.checksum] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+    bytes
+}
+
+fn decode_header(bytes: &[u8]) -> Result<Vec<Entry>, RegionError> {
+    if &bytes[..4] != MAGIC {
+        return corrupt("not a region file");
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != FORMAT_VERSION {
+        return corrupt(format!("unsupported format version {version}"));
+    }
+    if bytes[6] as isize != REGION_SIZE {
+        return corrupt(format!("unsupported region size {}", bytes[6]));
+    }
+    let (table, checksum) = bytes.split_at(HEADER_LEN - 4);
+    if crc32(table) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+        return corrupt("header checksum mismatch");
+    }
+    let u32_at = |i: usize| u32::from_le_bytes(table[i..i + 4].try_into().unwrap());
+    Ok((0..CELLS)
+        .map(|cell| {
+            let start = PREAMBLE_LEN + cell * ENTRY_LEN;
+            Entry {
+                offset: u32_at(start),
+                length: u32_at(start + 4),
+                checksum: u32_at(start + 8),
+            }
+        })
+        .collect())
+}
+
+/// Palette of the blocks in the chunk followed by runs of palette indices.
+/// Blocks are stored by value, so files survive changes of the registry order
+fn encode_chunk(chunk: &Chunk, registry: &BlockRegistry) -> Vec<u8> {
+    let mut palette: Vec<Voxel> = Vec::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for_each_voxel(|pos| {
+        let voxel = chunk.get(pos);
+        let index = match palette.iter().position(|v| *v == voxel) {
+            Some(index) => index,
+            None => {
+                palette.push(voxel);
+                palette.len() - 1
+            }
+        } as u16;
+        match runs.last_mut() {
+            Some((length, last)) if *last == index && *length < u16::MAX => *length += 1,
+            _ => runs.push((1, index)),
+        }
+    });
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for voxel in palette {
+        encode_block(&mut bytes, &registry[voxel]);
+    }
+    for (length, index) in runs {
+        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.extend_from_slice(&index.to_le_bytes());
+    }
+    bytes
+}
+
+/// Name, color and flags. Names are cut to 255 bytes, at a char boundary
+/// so they still decode
+pub(super) fn encode_block(bytes: &mut Vec<u8>, block: &Block) {
+    let mut len = block.name.len().min(u8::MAX as usize);
+    while !block.name.is_char_boundary(len) {
+        len -= 1;
+    }
+    bytes.push(len as u8);
+    bytes.extend_from_slice(&block.name.as_bytes()[..len]);
+    bytes.extend_from_slice(&block.color);
+    bytes.extend_from_slice(&[block.opaque as u8, block.emissive, block.solid as u8]);
+}
+
+/// Counterpart of `encode_block`
+pub(super) fn decode_block(reader: &mut ByteReader) -> Result<Block, BadData> {
+    let name_len = reader.take(1)?[0] as usize;
+    let Ok(name) = String::from_utf8(reader.take(name_len)?.to_vec()) else {
+        return Err(BadData("block name is not utf-8"));
+    };
+    let color = reader.array()?;
+    let [opaque, emissive, solid] = reader.array()?;
+    Ok(Block {
+        name,
+        color,
+        opaque: opaque != 0,
+        emissive,
+        solid: solid != 0,
+    })
+}
+
+/// Why data read by a `ByteReader` is malformed, becomes the error of the file format
+pub(super) struct BadData(pub &'static str);
+
+impl From<BadData> for RegionError {
+    fn from(BadData(reason): BadData) -> Self {
+        RegionError::Corrupt(reason.into())
+    }
+}
+
+/// Reads little endian data front to back
+pub(super) struct ByteReader<'a> {
+    /// Not read yet
+    pub bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], BadData> {
+        if self.bytes.len() < n {
+            return Err(BadData("data ends early"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], BadData> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u16(&mut self) -> Result<u16, BadData> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, BadData> {
+        self.array().map(u32::from_le_bytes)
+    }
+}
+
+fn decode_chunk(bytes: &[u8], registry: &mut BlockRegistry) -> Result<Chunk, RegionError> {
+    let mut reader = ByteReader { bytes };
+
+    let palette_len = reader.u16()?;
+    let mut palette = Vec::with_capacity(palette_len as usize);
+    for _ in 0..palette_len {
+        // blocks already registered keep their id
+        palette.push(registry.register(decode_block(&mut reader)?));
+    }
+
+    let mut voxels = Vec::with_capacity(Chunk::DIMENSIONS.pow(3));
+    while voxels.len() < Chunk::DIMENSIONS.pow(3) {
+        let length = reader.u16()? as usize;
+        let index = reader.u16()? as usize;
+        let Some(&voxel) = palette.get(index) else {
+            return corrupt("palette index out of range");
+        };
+        voxels.extend(std::iter::repeat_n(voxel, length));
+    }
+    if voxels.len() != Chunk::DIMENSIONS.pow(3) || !reader.bytes.is_empty() {
+        return corrupt("chunk has the wrong number of voxels");
+    }
+
+    if let [voxel] = palette[..] {
+        return Ok(Chunk::filled(voxel));
+    }
+    let mut chunk = Chunk::empty();
+    let mut voxels = voxels.into_iter();
+    for_each_voxel(|pos| chunk.set(pos, voxels.next().unwrap()));
+    Ok(chunk)
+}
+
+fn for_each_voxel(mut f: impl FnMut([usize; 3])) {
+    const D: usize = Chunk::DIMENSIONS;
+    for z in 0..D {
+        for y in 0..D {
+            for x in 0..D {
+                f([x, y, z]);
+            }
+        }
+    }
+}
+
+/// CRC-32 as used by zip and png
+pub(super) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod region_tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::modules::logic::{
+        block_registry::{Block, BlockRegistry},
+        chunk::Chunk,
+        chunk_streamer::{ChunkSource, ChunkStreamer, StreamingSettings},
+        scene::Scene,
+        test_utils::for_each_local,
+        voxel::AIR,
+        world_generator::{HeightmapGenerator, HeightmapSettings},
+    };
+
+    use super::{crc32, PersistentWorld, RegionError, RegionStorage, HEADER_LEN};
+
+    /// Removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("voxel_engine_{name}_{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::default();
+        registry.register(Block::new("stone", [125, 125, 125, 255]));
+        registry.register(Block::new("glass", [200, 220, 255, 100]));
+        registry
+    }
+
+    fn world(dir: &TempDir) -> PersistentWorld<HeightmapGenerator> {
+        PersistentWorld {
+            storage: RegionStorage::new(&dir.0),
+            generator: HeightmapGenerator::new(
+                0,
+                HeightmapSettings::default(),
+                &mut BlockRegistry::default(),
+            ),
+        }
+    }
+
+    fn sample_chunk() -> Chunk {
+        let mut chunk = Chunk::empty();
+        for i in 0..32 {
+            chunk.set([i, (i * 7) % 32, 31 - i], 1);
+            chunk.set([i, 0, 0], 2);
+        }
+        chunk
+    }
+
+    fn assert_same(a: &Chunk, b: &Chunk) {
+        for_each_local(|pos| assert_eq!(a.get(pos), b.get(pos), "{pos:?}"));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = TempDir::new("round_trip");
+        let registry = registry();
+        let chunk = sample_chunk();
+        let full = Chunk::filled(1);
+        let mut storage = RegionStorage::new(&dir.0);
+        storage
+            .save_chunks([([0, 0, 0], &chunk), ([-1, 3, 9], &full)], &registry)
+            .unwrap();
+        // a second save keeps the other chunks of the region
+        storage
+            .save_chunks([([1, 0, 0], &full)], &registry)
+            .unwrap();
+
+        // the registry of the loading side may differ
+        let mut other = BlockRegistry::default();
+        other.register(Block::new("dirt", [134, 96, 67, 255]));
+        let mut storage = RegionStorage::new(&dir.0);
+        let loaded = storage.load_chunk([0, 0, 0], &mut other).unwrap().unwrap();
+        let stone = other.id("stone").unwrap();
+        let glass = other.id("glass").unwrap();
+        assert!(!other[glass].opaque);
+        for_each_local(|pos| {
+            let expected = match chunk.get(pos) {
+                1 => stone,
+                2 => glass,
+                _ => AIR,
+            };
+            assert_eq!(expected, loaded.get(pos));
+        });
+
+        let mut registry = registry;
+        for idx in [[-1, 3, 9], [1, 0, 0]] {
+            let loaded = storage.load_chunk(idx, &mut registry).unwrap().unwrap();
+            assert_same(&full, &loaded);
+        }
+        assert!(storage
+            .load_chunk([2, 0, 0], &mut registry)
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .load_chunk([50, 0, 0], &mut registry)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_save_modified() {
+        let dir = TempDir::new("save_modified");
+        let mut scene = Scene::new(registry());
+        scene.insert_chunk([0, 0, 0], Chunk::filled(1));
+        scene.set_voxel([40, 5, -3], 2);
+        let mut streamer = ChunkStreamer::new(world(&dir), StreamingSettings::default());
+        streamer.save_modified(&mut scene).unwrap();
+        assert!(scene.take_modified().is_empty());
+        let mut storage = RegionStorage::new(&dir.0);
+
+        let mut registry = registry();
+        // inserted chunks are not edits
+        assert!(storage
+            .load_chunk([0, 0, 0], &mut registry)
+            .unwrap()
+            .is_none());
+        let loaded = storage
+            .load_chunk([1, 0, -1], &mut registry)
+            .unwrap()
+            .unwrap();
+        assert_same(scene.get_chunk([1, 0, -1]).unwrap(), &loaded);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let dir = TempDir::new("corrupt");
+        let registry = registry();
+        let mut storage = RegionStorage::new(&dir.0);
+        storage
+            .save_chunks([([0, 0, 0], &sample_chunk())], &registry)
+            .unwrap();
+        let path = dir.0.join("r.0.0.0.region");
+        let original = fs::read(&path).unwrap();
+
+        let load = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            RegionStorage::new(&dir.0).load_chunk([0, 0, 0], &mut registry.clone())
+        };
+        assert!(load(&original).unwrap().is_some());
+
+        let mut data = original.clone();
+        data[HEADER_LEN + 10] ^= 0x40;
+        assert!(matches!(load(&data), Err(RegionError::Corrupt(_))));
+
+        let mut header = original.clone();
+        header[20] ^= 1;
+        assert!(matches!(load(&header), Err(RegionError::Corrupt(_))));
+
+        let mut version = original.clone();
+        version[4] = 99;
+        assert!(matches!(load(&version), Err(RegionError::Corrupt(_))));
+
+        assert!(matches!(
+            load(&original[..100]),
+            Err(RegionError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn test_save_over_corrupt() {
+        let dir = TempDir::new("save_over_corrupt");
+        let registry = registry();
+        fs::create_dir_all(&dir.0).unwrap();
+        let path = dir.0.join("r.0.0.0.region");
+        fs::write(&path, b"not a region").unwrap();
+
+        // not generated, a save would then replace the saved chunk
+        let mut world = world(&dir);
+        assert!(world.load([0, 0, 0], &mut registry.clone()).is_err());
+
+        // the damaged file is moved aside instead of failing every save
+        let full = Chunk::filled(1);
+        world.save(&[([0, 0, 0], &full)], &registry).unwrap();
+        let recovered = world.take_recovered();
+        assert_eq!(1, recovered.len());
+        assert_eq!([0, 0, 0], recovered[0].region);
+        assert_eq!(
+            b"not a region".to_vec(),
+            fs::read(&recovered[0].moved_to).unwrap()
+        );
+        assert!(world.take_recovered().is_empty());
+        let loaded = RegionStorage::new(&dir.0)
+            .load_chunk([0, 0, 0], &mut registry.clone())
+            .unwrap()
+            .unwrap();
+        assert_same(&full, &loaded);
+    }
+
+    #[test]
+    fn test_long_name() {
+        let dir = TempDir::new("long_name");
+        let mut registry = BlockRegistry::default();
+        // two bytes per char, 255 would split one
+        let block = registry.register(Block::new("é".repeat(200), [1, 2, 3, 255]));
+        let mut storage = RegionStorage::new(&dir.0);
+        storage
+            .save_chunks([([0, 0, 0], &Chunk::filled(block))], &registry)
+            .unwrap();
+
+        let mut other = BlockRegistry::default();
+        let loaded = storage.load_chunk([0, 0, 0], &mut other).unwrap().unwrap();
+        assert_eq!("é".repeat(127), other[loaded.get([0, 0, 0])].name);
+    }
+}
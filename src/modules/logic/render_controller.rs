@@ -1,56 +1,108 @@
-use std::{rc::Rc, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+    thread,
+};
 
 use vulkano::{
-    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
         allocator::StandardCommandBufferAllocator, RenderPassBeginInfo, SubpassBeginInfo,
         SubpassEndInfo,
     },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
     format::ClearValue,
     image::view::ImageView,
     memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
-    pipeline::{graphics::viewport::Viewport, GraphicsPipeline, Pipeline},
+    pipeline::{graphics::viewport::Viewport, GraphicsPipeline, Pipeline, PipelineBindPoint},
     render_pass::RenderPass,
 };
 
 use crate::modules::{
-    math::{angle::Angle, cg::*, mat::*, vec::VecMult},
+    math::{angle::Angle, cg::*, mat::*, vec::*},
     renderer::{queue::QueueType, Renderer},
 };
 
 use super::{
+    block_registry::BlockRegistry,
     camera::Camera,
-    chunk_mesher::{self, ChunkMeshVertex},
-    chunk_render::{self, ChunkPushConstant},
-    scene::Scene,
+    chunk::Chunk,
+    chunk_mesh_worker::{ChunkMeshWorkers, MeshJob},
+    chunk_mesher::{self, ChunkMeshVertex, MeshSettings, MeshingStrategy},
+    chunk_render::{self, ChunkPushConstant, BLOCK_COLORS_BINDING},
+    outline_render::{self, OutlinePushConstant, OutlineVertex},
+    scene::{ChunkIndex, Scene, WorldPos},
 };
 
 pub struct RenderController {
     renderer: Renderer,
 
-    scene: Rc<Scene>,
+    scene: Rc<RefCell<Scene>>,
     frustum: PerspectiveFrustum,
 
     cmd_allocator: Arc<StandardCommandBufferAllocator>,
     mem_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_allocator: StandardDescriptorSetAllocator,
 
     render_pass: Arc<RenderPass>,
     depth_image: Arc<ImageView>,
     chunk_pipeline: Arc<GraphicsPipeline>,
+    outline_pipeline: Arc<GraphicsPipeline>,
+    outline_vertices: Subbuffer<[OutlineVertex]>,
+    /// Voxel drawn with an outline
+    target: Option<WorldPos>,
 
-    chunk_vertices: Vec<(Subbuffer<[ChunkMeshVertex]>, [isize; 3])>,
+    mesh_workers: ChunkMeshWorkers,
+    /// Dirty chunks that did not fit into the worker queue yet
+    pending_chunks: HashSet<ChunkIndex>,
+    chunk_meshes: HashMap<ChunkIndex, Subbuffer<[ChunkMeshVertex]>>,
+    /// Indices of the largest mesh so far, every mesh is drawn with a prefix
+    quad_indices: Subbuffer<[u32]>,
+    /// Uploaded again when the registry gains blocks
+    block_colors: Arc<PersistentDescriptorSet>,
+    block_count: usize,
 }
 
+const MESH_QUEUE_CAPACITY: usize = 64;
+
 impl RenderController {
-    pub fn new(renderer: Renderer, scene: Rc<Scene>) -> Self {
+    pub fn new(renderer: Renderer, scene: Rc<RefCell<Scene>>) -> Self {
         let cmd_allocator = Arc::new(renderer.create_command_buffer_allocator());
         let mem_allocator = Arc::new(renderer.create_memory_allocator());
+        let descriptor_allocator = renderer.create_descriptor_set_allocator();
 
         let render_pass = renderer.default_render_pass_with_depth(1);
         let depth_buffer = renderer.create_depth_buffer(mem_allocator.clone());
         let chunk_pipeline = renderer.create_graphics_pipeline(|| {
             chunk_render::chunk_graphics_pipeline(&renderer, render_pass.clone().first_subpass())
         });
+        let outline_pipeline = renderer.create_graphics_pipeline(|| {
+            outline_render::outline_graphics_pipeline(
+                &renderer,
+                render_pass.clone().first_subpass(),
+            )
+        });
+        let outline_vertices = upload(
+            &mem_allocator,
+            BufferUsage::VERTEX_BUFFER,
+            outline_render::voxel_outline(),
+        );
+        let quad_indices = upload(
+            &mem_allocator,
+            BufferUsage::INDEX_BUFFER,
+            chunk_mesher::quad_indices(1).collect(),
+        );
+        let block_count = scene.borrow().registry().len();
+        let block_colors = upload_block_colors(
+            &descriptor_allocator,
+            &mem_allocator,
+            &chunk_pipeline,
+            scene.borrow().registry(),
+        );
 
         let frustum = PerspectiveFrustum {
             near: 1e-1,
@@ -59,28 +111,14 @@ impl RenderController {
             ar: renderer.swapchain_extent().aspect_ratio(),
         };
 
-        let allocation_info = AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        };
-        let create_info = BufferCreateInfo {
-            usage: BufferUsage::VERTEX_BUFFER,
+        let mesh_settings = MeshSettings {
+            strategy: MeshingStrategy::Greedy,
             ..Default::default()
         };
-        let mut chunk_vertices = Vec::new();
-        for (idx, chunk) in scene.get_chunks() {
-            let buffer = Buffer::from_iter(
-                mem_allocator.clone(),
-                create_info.clone(),
-                allocation_info.clone(),
-                chunk_mesher::mesh(chunk),
-            )
-            .unwrap();
-            chunk_vertices.push((buffer, idx.clone()));
-        }
+        let mesh_threads =
+            thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1).max(1));
 
-        Self {
+        let mut render_controller = Self {
             renderer,
 
             scene,
@@ -88,12 +126,84 @@ impl RenderController {
 
             cmd_allocator,
             mem_allocator,
+            descriptor_allocator,
 
             render_pass,
             depth_image: depth_buffer,
             chunk_pipeline,
+            outline_pipeline,
+            outline_vertices,
+            target: None,
 
-            chunk_vertices,
+            mesh_workers: ChunkMeshWorkers::new(mesh_threads, MESH_QUEUE_CAPACITY, mesh_settings),
+            pending_chunks: HashSet::new(),
+            chunk_meshes: HashMap::new(),
+            quad_indices,
+            block_colors,
+            block_count,
+        };
+        render_controller.update_chunk_meshes();
+        render_controller
+    }
+
+    /// Sends the chunks the scene marked as dirty to the mesh workers
+    /// and uploads the meshes that are done
+    pub fn update_chunk_meshes(&mut self) {
+        let mut scene = self.scene.borrow_mut();
+        self.pending_chunks.extend(scene.take_dirty());
+        if scene.registry().len() != self.block_count {
+            self.block_count = scene.registry().len();
+            self.block_colors = upload_block_colors(
+                &self.descriptor_allocator,
+                &self.mem_allocator,
+                &self.chunk_pipeline,
+                scene.registry(),
+            );
+        }
+
+        let camera = scene.camera.borrow().pos;
+        let distance = |idx: ChunkIndex| {
+            let center = idx.map(|c| (c as f32 + 0.5) * Chunk::DIMENSIONS as f32);
+            center.sub(camera).len2()
+        };
+        let mut pending: Vec<_> = self.pending_chunks.drain().collect();
+        pending.sort_by(|a, b| distance(*a).total_cmp(&distance(*b)));
+        for idx in pending {
+            if self.mesh_workers.is_full() {
+                self.pending_chunks.insert(idx);
+                continue;
+            }
+            match MeshJob::snapshot(&scene, idx, distance(idx)) {
+                Some(job) => {
+                    if let Err(job) = self.mesh_workers.submit(job) {
+                        self.pending_chunks.insert(job.idx);
+                    }
+                }
+                None => {
+                    self.mesh_workers.cancel(idx);
+                    self.chunk_meshes.remove(&idx);
+                }
+            }
+        }
+
+        for (idx, mesh) in self.mesh_workers.poll() {
+            if mesh.is_empty() {
+                self.chunk_meshes.remove(&idx);
+                continue;
+            }
+            if self.quad_indices.len() < 6 * mesh.quad_count() as u64 {
+                self.quad_indices = upload(
+                    &self.mem_allocator,
+                    BufferUsage::INDEX_BUFFER,
+                    chunk_mesher::quad_indices(mesh.quad_count()).collect(),
+                );
+            }
+            let vertices = upload(
+                &self.mem_allocator,
+                BufferUsage::VERTEX_BUFFER,
+                mesh.vertices,
+            );
+            self.chunk_meshes.insert(idx, vertices);
         }
     }
 
@@ -105,7 +215,9 @@ impl RenderController {
         self.frustum.ar = extent.aspect_ratio();
     }
 
-    pub fn draw_frame(&self) {
+    pub fn draw_frame(&mut self) {
+        self.update_chunk_meshes();
+
         let (mut cmd_builder, _) = self
             .renderer
             .create_command_buffer_builder(QueueType::GraphicsPresent, &self.cmd_allocator);
@@ -137,10 +249,21 @@ impl RenderController {
                     )
                     .unwrap()
                     .bind_pipeline_graphics(self.chunk_pipeline.clone())
+                    .unwrap()
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        self.chunk_pipeline.layout().clone(),
+                        0,
+                        self.block_colors.clone(),
+                    )
+                    .unwrap()
+                    .bind_index_buffer(self.quad_indices.clone())
                     .unwrap();
-                for (subbuffer, idx) in &self.chunk_vertices {
+                let scene = self.scene.borrow();
+                let light = scene.light();
+                for (idx, vertices) in &self.chunk_meshes {
                     let projection = self.frustum.projection_matrix();
-                    let view = self.scene.camera.borrow().view_matrix();
+                    let view = scene.camera.borrow().view_matrix();
                     let model = [idx[0] as f32, idx[1] as f32, idx[2] as f32]
                         .mult(32.0)
                         .translation_matrix();
@@ -148,16 +271,39 @@ impl RenderController {
                         .push_constants(
                             self.chunk_pipeline.layout().clone(),
                             0,
-                            ChunkPushConstant {
-                                pvm: projection.mult(view).mult(model).trans(),
-                            },
+                            ChunkPushConstant::new(
+                                projection.mult(view).mult(model).trans(),
+                                light,
+                            ),
                         )
                         .unwrap();
 
                     cmd_builder
-                        .bind_vertex_buffers(0, subbuffer.clone())
+                        .bind_vertex_buffers(0, vertices.clone())
                         .unwrap()
-                        .draw(subbuffer.len() as u32, 1, 0, 0)
+                        // 6 indices per 4 vertices
+                        .draw_indexed(vertices.len() as u32 / 4 * 6, 1, 0, 0, 0)
+                        .unwrap();
+                }
+                if let Some(target) = self.target {
+                    let projection = self.frustum.projection_matrix();
+                    let view = scene.camera.borrow().view_matrix();
+                    let model = target.map(|c| c as f32).translation_matrix();
+                    cmd_builder
+                        .bind_pipeline_graphics(self.outline_pipeline.clone())
+                        .unwrap()
+                        .push_constants(
+                            self.outline_pipeline.layout().clone(),
+                            0,
+                            OutlinePushConstant {
+                                pvm: projection.mult(view).mult(model).trans(),
+                                color: [1.0, 1.0, 1.0, 1.0],
+                            },
+                        )
+                        .unwrap()
+                        .bind_vertex_buffers(0, self.outline_vertices.clone())
+                        .unwrap()
+                        .draw(self.outline_vertices.len() as u32, 1, 0, 0)
                         .unwrap();
                 }
                 cmd_builder
@@ -173,6 +319,11 @@ impl RenderController {
         // }
     }
 
+    /// Voxel to outline, e.g. the one the camera looks at
+    pub fn set_target(&mut self, target: Option<WorldPos>) {
+        self.target = target;
+    }
+
     pub fn fov_plus(&mut self) {
         self.frustum.fov += Angle::from_deg(1.0);
     }
@@ -181,3 +332,40 @@ impl RenderController {
         self.frustum.fov -= Angle::from_deg(1.0);
     }
 }
+
+fn upload_block_colors(
+    descriptor_allocator: &StandardDescriptorSetAllocator,
+    mem_allocator: &Arc<StandardMemoryAllocator>,
+    chunk_pipeline: &GraphicsPipeline,
+    registry: &BlockRegistry,
+) -> Arc<PersistentDescriptorSet> {
+    let colors = upload(
+        mem_allocator,
+        BufferUsage::STORAGE_BUFFER,
+        chunk_render::block_colors(registry),
+    );
+    PersistentDescriptorSet::new(
+        descriptor_allocator,
+        chunk_pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::buffer(BLOCK_COLORS_BINDING, colors)],
+        [],
+    )
+    .unwrap()
+}
+
+fn upload<T: BufferContents>(
+    mem_allocator: &Arc<StandardMemoryAllocator>,
+    usage: BufferUsage,
+    data: Vec<T>,
+) -> Subbuffer<[T]> {
+    let allocation_info = AllocationCreateInfo {
+        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        ..Default::default()
+    };
+    let create_info = BufferCreateInfo {
+        usage,
+        ..Default::default()
+    };
+    Buffer::from_iter(mem_allocator.clone(), create_info, allocation_info, data).unwrap()
+}
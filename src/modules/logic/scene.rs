@@ -1,29 +1,45 @@
 use std::{
     cell::{Cell, RefCell},
-    collections::{hash_map::Iter, HashMap},
+    collections::{hash_map::Iter, HashMap, HashSet},
+    sync::Arc,
 };
 
 use super::{
+    block_registry::BlockRegistry,
     camera::{Camera, OrientedCamera, TrackingCamera},
     chunk::Chunk,
+    chunk_mesher::ChunkNeighbours,
+    history::{History, VoxelEdit},
     light::Light,
+    voxel::{Voxel, AIR},
 };
 use crate::modules::math::{angle::Angle, cg::Orientation, quaternion::Quaternion, vec::*};
 
-type ChunkIndex = [isize; 3];
+pub type ChunkIndex = [isize; 3];
+/// Voxel position in the world, chunk `[0, 0, 0]` spans 0..32 on every axis
+pub type WorldPos = [isize; 3];
+
 pub struct Scene {
     chunks: HashMap<ChunkIndex, Chunk>,
+    /// Chunks whose mesh is out of date
+    dirty: HashSet<ChunkIndex>,
+    /// Chunks edited since they were last saved
+    modified: HashSet<ChunkIndex>,
+    registry: Arc<BlockRegistry>,
+    history: History,
     light: Light,
     pub camera: RefCell<OrientedCamera>,
 }
 
-impl Default for Scene {
-    fn default() -> Self {
-        let mut chunks = HashMap::new();
-        chunks.insert([0, 0, 0], Chunk::random());
-        chunks.insert([1, 0, 0], Chunk::cat());
+impl Scene {
+    /// Scene without any chunks
+    pub fn new(registry: BlockRegistry) -> Self {
         Self {
-            chunks,
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+            modified: HashSet::new(),
+            registry: Arc::new(registry),
+            history: History::default(),
             light: Light::default(),
             // camera: RefCell::new(TrackingCamera {
             //     pos: [0.0, -5.0, 0.0],
@@ -45,4 +61,240 @@ impl Scene {
     pub fn get_chunks(&self) -> Iter<ChunkIndex, Chunk>{
         self.chunks.iter()
     }
+
+    /// Replaces the chunk at `idx`, returns the previous one
+    pub fn insert_chunk(&mut self, idx: ChunkIndex, chunk: Chunk) -> Option<Chunk> {
+        self.dirty.insert(idx);
+        self.mark_neighbours_dirty(idx);
+        self.chunks.insert(idx, chunk)
+    }
+
+    /// Edits of the chunk that were not saved are lost
+    pub fn remove_chunk(&mut self, idx: ChunkIndex) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&idx)?;
+        self.modified.remove(&idx);
+        self.dirty.insert(idx);
+        self.mark_neighbours_dirty(idx);
+        Some(chunk)
+    }
+
+    /// Chunk index and position inside of it
+    pub fn locate(pos: WorldPos) -> (ChunkIndex, [usize; 3]) {
+        const D: isize = Chunk::DIMENSIONS as isize;
+        (
+            pos.map(|c| c.div_euclid(D)),
+            pos.map(|c| c.rem_euclid(D) as usize),
+        )
+    }
+
+    /// Voxels of chunks that are not loaded are air
+    pub fn get_voxel(&self, pos: WorldPos) -> Voxel {
+        let (idx, local) = Self::locate(pos);
+        self.chunks.get(&idx).map_or(AIR, |chunk| chunk.get(local))
+    }
+
+    /// Creates the chunk if it is missing, returns the replaced voxel
+    pub fn set_voxel(&mut self, pos: WorldPos, voxel: Voxel) -> Voxel {
+        let (idx, local) = Self::locate(pos);
+        let chunk = match self.chunks.get_mut(&idx) {
+            Some(chunk) => chunk,
+            None if voxel == AIR => return AIR,
+            None => {
+                // faces of the neighbours may be hidden by the new chunk
+                self.insert_chunk(idx, Chunk::empty());
+                self.chunks.get_mut(&idx).unwrap()
+            }
+        };
+        let previous = chunk.get(local);
+        if previous == voxel {
+            return previous;
+        }
+        chunk.set(local, voxel);
+        self.history.record(VoxelEdit {
+            pos,
+            before: previous,
+            after: voxel,
+        });
+
+        self.modified.insert(idx);
+        self.dirty.insert(idx);
+        for axis in 0..3 {
+            let side = match local[axis] {
+                0 => -1,
+                c if c == Chunk::DIMENSIONS - 1 => 1,
+                _ => continue,
+            };
+            let mut neighbour = idx;
+            neighbour[axis] += side;
+            if self.chunks.contains_key(&neighbour) {
+                self.dirty.insert(neighbour);
+            }
+        }
+        previous
+    }
+
+    /// Records the following voxel edits as one undo step
+    pub fn begin_transaction(&mut self) {
+        self.history.begin();
+    }
+
+    pub fn commit_transaction(&mut self) {
+        self.history.commit();
+    }
+
+    /// Reverts the latest transaction, edits of chunks that are no longer
+    /// loaded are skipped. False if there is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        let Some(transaction) = self.history.take_undo() else {
+            return false;
+        };
+        for edit in transaction.edits.iter().rev() {
+            self.restore_voxel(edit.pos, edit.before);
+        }
+        self.history.undone(transaction);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(transaction) = self.history.take_redo() else {
+            return false;
+        };
+        for edit in &transaction.edits {
+            self.restore_voxel(edit.pos, edit.after);
+        }
+        self.history.redone(transaction);
+        true
+    }
+
+    /// Outside of a transaction, so it is not recorded again
+    fn restore_voxel(&mut self, pos: WorldPos, voxel: Voxel) {
+        if self.chunks.contains_key(&Self::locate(pos).0) {
+            self.set_voxel(pos, voxel);
+        }
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Chunks changed since the last call, including removed ones
+    pub fn take_dirty(&mut self) -> HashSet<ChunkIndex> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn is_modified(&self, idx: ChunkIndex) -> bool {
+        self.modified.contains(&idx)
+    }
+
+    /// Chunks edited since the last call, e.g. to save them
+    pub fn take_modified(&mut self) -> HashSet<ChunkIndex> {
+        std::mem::take(&mut self.modified)
+    }
+
+    fn mark_neighbours_dirty(&mut self, idx: ChunkIndex) {
+        for axis in 0..3 {
+            for side in [-1, 1] {
+                let mut neighbour = idx;
+                neighbour[axis] += side;
+                if self.chunks.contains_key(&neighbour) {
+                    self.dirty.insert(neighbour);
+                }
+            }
+        }
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+
+    /// Copies the registry first if it is shared
+    pub fn registry_mut(&mut self) -> &mut BlockRegistry {
+        Arc::make_mut(&mut self.registry)
+    }
+
+    /// Registry handle for meshing off the main thread
+    pub fn shared_registry(&self) -> Arc<BlockRegistry> {
+        self.registry.clone()
+    }
+
+    pub fn light(&self) -> &Light {
+        &self.light
+    }
+
+    pub fn neighbours(&self, idx: ChunkIndex) -> ChunkNeighbours {
+        ChunkNeighbours::from_fn(|offset| {
+            self.get_chunk([idx[0] + offset[0], idx[1] + offset[1], idx[2] + offset[2]])
+        })
+    }
+}
+
+#[cfg(test)]
+mod scene_tests {
+    use std::collections::HashSet;
+
+    use crate::modules::logic::{block_registry::BlockRegistry, chunk::Chunk, voxel::AIR};
+
+    use super::Scene;
+
+    #[test]
+    fn test_locate() {
+        assert_eq!(([0, 0, 0], [0, 5, 31]), Scene::locate([0, 5, 31]));
+        assert_eq!(([-1, 0, 1], [31, 0, 0]), Scene::locate([-1, 0, 32]));
+        assert_eq!(([-1, -2, -1], [0, 31, 1]), Scene::locate([-32, -33, -31]));
+    }
+
+    #[test]
+    fn test_get_set() {
+        let mut scene = Scene::new(BlockRegistry::default());
+        assert_eq!(AIR, scene.get_voxel([-5, 3, 100]));
+        // clearing nothing does not create chunks
+        assert_eq!(AIR, scene.set_voxel([-5, 3, 100], AIR));
+        assert!(scene.get_chunk([-1, 0, 3]).is_none());
+
+        assert_eq!(AIR, scene.set_voxel([-5, 3, 100], 1));
+        assert_eq!(1, scene.get_voxel([-5, 3, 100]));
+        assert_eq!(1, scene.get_chunk([-1, 0, 3]).unwrap().get([27, 3, 4]));
+        assert_eq!(1, scene.set_voxel([-5, 3, 100], 2));
+        assert_eq!(AIR, scene.get_voxel([-5, 3, 101]));
+    }
+
+    #[test]
+    fn test_dirty() {
+        let mut scene = Scene::new(BlockRegistry::default());
+        scene.set_voxel([5, 5, 5], 1);
+        scene.set_voxel([-5, 5, 5], 1);
+        assert_eq!(HashSet::from([[0, 0, 0], [-1, 0, 0]]), scene.take_dirty());
+        assert!(scene.take_dirty().is_empty());
+
+        // same voxel again changes nothing
+        scene.set_voxel([5, 5, 5], 1);
+        assert!(scene.take_dirty().is_empty());
+
+        // borders touch the neighbour if it exists
+        scene.set_voxel([0, 5, 5], 1);
+        assert_eq!(HashSet::from([[0, 0, 0], [-1, 0, 0]]), scene.take_dirty());
+        scene.set_voxel([31, 0, 31], 1);
+        assert_eq!(HashSet::from([[0, 0, 0]]), scene.take_dirty());
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut scene = Scene::new(BlockRegistry::default());
+        assert!(scene.insert_chunk([0, 0, 0], Chunk::filled(1)).is_none());
+        assert!(scene.insert_chunk([5, 5, 5], Chunk::filled(1)).is_none());
+        scene.take_dirty();
+
+        assert!(scene.remove_chunk([1, 0, 0]).is_none());
+        assert!(scene.take_dirty().is_empty());
+
+        assert!(scene.insert_chunk([0, 0, 1], Chunk::empty()).is_none());
+        assert_eq!(HashSet::from([[0, 0, 1], [0, 0, 0]]), scene.take_dirty());
+        assert!(scene.remove_chunk([0, 0, 0]).is_some());
+        assert_eq!(HashSet::from([[0, 0, 1], [0, 0, 0]]), scene.take_dirty());
+        assert_eq!(AIR, scene.get_voxel([0, 0, 0]));
+    }
 }
@@ -0,0 +1,446 @@
+use std::{collections::HashSet, fmt, fs, io, path::Path};
+
+use super::{
+    block_registry::BlockRegistry,
+    region::{crc32, decode_block, encode_block, BadData, ByteReader},
+    scene::{ChunkIndex, Scene, WorldPos},
+    voxel::{Voxel, AIR},
+};
+
+const MAGIC: &[u8; 4] = b"VXSC";
+pub const FORMAT_VERSION: u16 = 1;
+/// Length and palette index of a run of voxels
+const RUN_LEN: usize = 6;
+/// Largest number of voxels, 32 MiB, keeps damaged files from allocating gigabytes
+pub const MAX_VOLUME: usize = 1 << 24;
+
+#[derive(Debug)]
+pub enum SchematicError {
+    Io(io::Error),
+    /// Not a schematic file of a known version or damaged
+    Corrupt(String),
+}
+
+impl fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchematicError::Io(error) => write!(f, "schematic io error: {error}"),
+            SchematicError::Corrupt(reason) => write!(f, "corrupt schematic: {reason}"),
+        }
+    }
+}
+
+impl From<io::Error> for SchematicError {
+    fn from(error: io::Error) -> Self {
+        SchematicError::Io(error)
+    }
+}
+
+impl From<BadData> for SchematicError {
+    fn from(BadData(reason): BadData) -> Self {
+        SchematicError::Corrupt(reason.into())
+    }
+}
+
+fn corrupt<T>(reason: impl Into<String>) -> Result<T, SchematicError> {
+    Err(SchematicError::Corrupt(reason.into()))
+}
+
+/// Box of voxels on its own, e.g. a clipboard or a prefab.
+/// Voxels are ids of the registry of the scene it was copied from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schematic {
+    size: [usize; 3],
+    /// x changes fastest, then y
+    voxels: Vec<Voxel>,
+}
+
+impl Schematic {
+    /// Filled with air
+    pub fn new(size: [usize; 3]) -> Self {
+        Self {
+            size,
+            voxels: vec![AIR; size[0] * size[1] * size[2]],
+        }
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        assert!(
+            x < self.size[0] && y < self.size[1] && z < self.size[2],
+            "{:?} is outside of a {:?} schematic",
+            [x, y, z],
+            self.size
+        );
+        x + self.size[0] * (y + self.size[1] * z)
+    }
+
+    pub fn get(&self, pos: [usize; 3]) -> Voxel {
+        self.voxels[self.index(pos)]
+    }
+
+    pub fn set(&mut self, pos: [usize; 3], voxel: Voxel) {
+        let index = self.index(pos);
+        self.voxels[index] = voxel;
+    }
+
+    fn for_each_pos(size: [usize; 3], mut f: impl FnMut([usize; 3])) {
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    f([x, y, z]);
+                }
+            }
+        }
+    }
+
+    /// Voxels between the inclusive corners, given in any order.
+    /// `None` if there are more than `MAX_VOLUME`
+    pub fn copy(scene: &Scene, a: WorldPos, b: WorldPos) -> Option<Self> {
+        let min = [0, 1, 2].map(|i| a[i].min(b[i]));
+        let size = [0, 1, 2].map(|i| a[i].abs_diff(b[i]) + 1);
+        volume(size)?;
+        let mut schematic = Self::new(size);
+        Self::for_each_pos(size, |pos| {
+            let world = [0, 1, 2].map(|i| min[i] + pos[i] as isize);
+            schematic.set(pos, scene.get_voxel(world));
+        });
+        Some(schematic)
+    }
+
+    /// Writes the voxels with their minimal corner at `origin`, air only
+    /// replaces voxels with `with_air`. Returns the chunks with changed voxels
+    pub fn paste(
+        &self,
+        scene: &mut Scene,
+        origin: WorldPos,
+        with_air: bool,
+    ) -> HashSet<ChunkIndex> {
+        let mut touched = HashSet::new();
+        Self::for_each_pos(self.size, |pos| {
+            let voxel = self.get(pos);
+            let world = [0, 1, 2].map(|i| origin[i] + pos[i] as isize);
+            if (voxel != AIR || with_air) && scene.get_voxel(world) != voxel {
+                scene.set_voxel(world, voxel);
+                touched.insert(Scene::locate(world).0);
+            }
+        });
+        touched
+    }
+
+    /// Turned counterclockwise by `quarter_turns` times 90 degrees
+    /// when looking down the positive `axis`
+    pub fn rotated(&self, axis: usize, quarter_turns: i32) -> Self {
+        let mut rotated = self.clone();
+        for _ in 0..quarter_turns.rem_euclid(4) {
+            rotated = rotated.rotated_once(axis);
+        }
+        rotated
+    }
+
+    fn rotated_once(&self, axis: usize) -> Self {
+        // the plane axes in right handed order, u turns into v
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut size = self.size;
+        size.swap(u, v);
+        let mut rotated = Self::new(size);
+        Self::for_each_pos(self.size, |pos| {
+            let mut turned = pos;
+            turned[u] = self.size[v] - 1 - pos[v];
+            turned[v] = pos[u];
+            rotated.set(turned, self.get(pos));
+        });
+        rotated
+    }
+
+    /// Flipped along `axis`
+    pub fn mirrored(&self, axis: usize) -> Self {
+        let mut mirrored = Self::new(self.size);
+        Self::for_each_pos(self.size, |pos| {
+            let mut flipped = pos;
+            flipped[axis] = self.size[axis] - 1 - pos[axis];
+            mirrored.set(flipped, self.get(pos));
+        });
+        mirrored
+    }
+
+    /// Size, the blocks used by value and runs of palette indices, then a
+    /// checksum. Loading registers missing blocks, so prefabs work in any world
+    pub fn encode(&self, registry: &BlockRegistry) -> Vec<u8> {
+        let mut palette: Vec<Voxel> = Vec::new();
+        let mut runs: Vec<(u32, u16)> = Vec::new();
+        for voxel in &self.voxels {
+            let index = match palette.iter().position(|v| v == voxel) {
+                Some(index) => index,
+                None => {
+                    palette.push(*voxel);
+                    palette.len() - 1
+                }
+            } as u16;
+            match runs.last_mut() {
+                Some((length, last)) if *last == index => *length += 1,
+                _ => runs.push((1, index)),
+            }
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        for c in self.size {
+            bytes.extend_from_slice(&(c as u32).to_le_bytes());
+        }
+        bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for voxel in palette {
+            encode_block(&mut bytes, &registry[voxel]);
+        }
+        for (length, index) in runs {
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8], registry: &mut BlockRegistry) -> Result<Self, SchematicError> {
+        let Some((content, checksum)) = bytes.split_last_chunk::<4>() else {
+            return corrupt("file is too short");
+        };
+        if crc32(content) != u32::from_le_bytes(*checksum) {
+            return corrupt("checksum mismatch");
+        }
+        let mut reader = ByteReader { bytes: content };
+        if reader.take(4)? != MAGIC {
+            return corrupt("not a schematic file");
+        }
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return corrupt(format!("unsupported format version {version}"));
+        }
+        let mut size = [0; 3];
+        for c in &mut size {
+            *c = reader.u32()? as usize;
+        }
+        let Some(volume) = volume(size) else {
+            return corrupt(format!("size {size:?} is too large"));
+        };
+
+        let palette_len = reader.u16()?;
+        let mut palette = Vec::with_capacity(palette_len as usize);
+        for _ in 0..palette_len {
+            palette.push(decode_block(&mut reader)?);
+        }
+
+        // the runs take up the rest of the file
+        let runs = reader.bytes;
+        if !runs.len().is_multiple_of(RUN_LEN) {
+            return corrupt("runs are cut off");
+        }
+        // palette indices until the whole file is known to be valid
+        let mut voxels = Vec::new();
+        for run in runs.chunks_exact(RUN_LEN) {
+            let mut run = ByteReader { bytes: run };
+            let length = run.u32()? as usize;
+            let index = run.u16()?;
+            if index as usize >= palette.len() {
+                return corrupt("palette index out of range");
+            }
+            if length > volume - voxels.len() {
+                return corrupt("too many voxels");
+            }
+            voxels.extend(std::iter::repeat_n(index, length));
+        }
+        if voxels.len() != volume {
+            return corrupt("too few voxels");
+        }
+
+        // blocks already registered keep their id
+        let palette: Vec<Voxel> = palette
+            .into_iter()
+            .map(|block| registry.register(block))
+            .collect();
+        for voxel in &mut voxels {
+            *voxel = palette[*voxel as usize];
+        }
+        Ok(Self { size, voxels })
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        registry: &BlockRegistry,
+    ) -> Result<(), SchematicError> {
+        Ok(fs::write(path, self.encode(registry))?)
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+        registry: &mut BlockRegistry,
+    ) -> Result<Self, SchematicError> {
+        Self::decode(&fs::read(path)?, registry)
+    }
+}
+
+/// `None` above `MAX_VOLUME`
+fn volume(size: [usize; 3]) -> Option<usize> {
+    let volume = size[0].checked_mul(size[1])?.checked_mul(size[2])?;
+    (volume <= MAX_VOLUME).then_some(volume)
+}
+
+#[cfg(test)]
+mod schematic_tests {
+    use crate::modules::logic::{
+        block_registry::{Block, BlockRegistry},
+        scene::Scene,
+        voxel::AIR,
+    };
+
+    use super::{crc32, volume, Schematic, SchematicError, MAX_VOLUME, RUN_LEN};
+
+    /// An L of three voxels in a 2x3x1 box, the corner at the origin
+    fn letter() -> Schematic {
+        let mut schematic = Schematic::new([2, 3, 1]);
+        schematic.set([0, 0, 0], 1);
+        schematic.set([1, 0, 0], 2);
+        schematic.set([0, 2, 0], 3);
+        schematic
+    }
+
+    #[test]
+    fn test_copy_paste() {
+        let mut registry = BlockRegistry::default();
+        let stone = registry.register(Block::new("stone", [128, 128, 128, 255]));
+        let mut scene = Scene::new(registry);
+        scene.set_voxel([-1, 5, 0], stone);
+        scene.set_voxel([1, 6, 2], stone);
+
+        let copy = Schematic::copy(&scene, [1, 6, 2], [-1, 4, 0]).unwrap();
+        assert_eq!([3, 3, 3], copy.size());
+        assert_eq!(stone, copy.get([0, 1, 0]));
+        assert_eq!(stone, copy.get([2, 2, 2]));
+
+        // across the border to the chunks with negative x
+        scene.set_voxel([-40, 0, 0], stone);
+        let touched = copy.paste(&mut scene, [-33, 0, 0], false);
+        assert_eq!(2, touched.len());
+        assert_eq!(stone, scene.get_voxel([-33, 1, 0]));
+        assert_eq!(stone, scene.get_voxel([-31, 2, 2]));
+        assert_eq!(AIR, scene.get_voxel([-32, 1, 0]));
+
+        let mut air = Schematic::new([1, 1, 1]);
+        assert!(air.paste(&mut scene, [-33, 1, 0], false).is_empty());
+        assert_eq!(1, air.paste(&mut scene, [-33, 1, 0], true).len());
+        assert_eq!(AIR, scene.get_voxel([-33, 1, 0]));
+        air.set([0, 0, 0], stone);
+        assert_eq!(stone, air.get([0, 0, 0]));
+    }
+
+    #[test]
+    fn test_rotate() {
+        let letter = letter();
+        let turned = letter.rotated(2, 1);
+        assert_eq!([3, 2, 1], turned.size());
+        // x turns into y, y into -x
+        assert_eq!(1, turned.get([2, 0, 0]));
+        assert_eq!(2, turned.get([2, 1, 0]));
+        assert_eq!(3, turned.get([0, 0, 0]));
+        assert_eq!(letter.rotated(2, -1), letter.rotated(2, 3));
+
+        for axis in 0..3 {
+            assert_eq!(letter, letter.rotated(axis, 4));
+            assert_eq!(
+                letter.rotated(axis, 2),
+                letter.rotated(axis, 1).rotated(axis, 1)
+            );
+        }
+        // y turns into z around x
+        let upright = letter.rotated(0, 1);
+        assert_eq!([2, 1, 3], upright.size());
+        assert_eq!(3, upright.get([0, 0, 2]));
+    }
+
+    #[test]
+    fn test_mirror() {
+        let letter = letter();
+        let mirrored = letter.mirrored(0);
+        assert_eq!([2, 3, 1], mirrored.size());
+        assert_eq!(2, mirrored.get([0, 0, 0]));
+        assert_eq!(3, mirrored.get([1, 2, 0]));
+        assert_eq!(letter, mirrored.mirrored(0));
+        assert_eq!(letter, letter.mirrored(2));
+        // two mirrors make a half turn
+        assert_eq!(letter.rotated(2, 2), letter.mirrored(0).mirrored(1));
+    }
+
+    #[test]
+    fn test_file() {
+        let mut registry = BlockRegistry::default();
+        // cut to 255 bytes without splitting a char
+        for name in ["a".into(), "b".into(), "é".repeat(200)] {
+            registry.register(Block::new(name, [1, 2, 3, 255]));
+        }
+        let letter = letter();
+        let bytes = letter.encode(&registry);
+
+        // block ids are looked up by name
+        let mut other = BlockRegistry::default();
+        let c = other.register(Block::new("é".repeat(127), [9, 9, 9, 255]));
+        let loaded = Schematic::decode(&bytes, &mut other).unwrap();
+        assert_eq!(letter.size(), loaded.size());
+        assert_eq!(c, loaded.get([0, 2, 0]));
+        assert_eq!("a", other[loaded.get([0, 0, 0])].name);
+        assert_eq!(AIR, loaded.get([1, 1, 0]));
+
+        let mut damaged = bytes.clone();
+        damaged[12] ^= 1;
+        let error = Schematic::decode(&damaged, &mut other).unwrap_err();
+        assert!(matches!(error, SchematicError::Corrupt(_)), "{error}");
+        assert!(Schematic::decode(&bytes[..5], &mut other).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_registry() {
+        let mut registry = BlockRegistry::default();
+        registry.register(Block::new("a", [1, 2, 3, 255]));
+        registry.register(Block::new("b", [4, 5, 6, 255]));
+        registry.register(Block::new("c", [7, 8, 9, 255]));
+        let bytes = letter().encode(&registry);
+
+        // the palette is intact, the last run is cut off
+        let mut bytes = bytes[..bytes.len() - 4 - RUN_LEN].to_vec();
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        let mut other = BlockRegistry::default();
+        let len = other.len();
+        assert!(Schematic::decode(&bytes, &mut other).is_err());
+        assert_eq!(len, other.len());
+        assert_eq!(None, other.id("a"));
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(Some(MAX_VOLUME), volume([4096, 4096, 1]));
+        assert_eq!(None, volume([usize::MAX, 2, 1]));
+        let scene = Scene::new(BlockRegistry::default());
+        assert!(Schematic::copy(&scene, [0, 0, 0], [4095, 4096, 0]).is_none());
+
+        let registry = BlockRegistry::default();
+        let bytes = Schematic::new([2, 2, 2]).encode(&registry);
+        // checksums are fixed up, so only the limits stop these
+        let decode = |edit: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes[..bytes.len() - 4].to_vec();
+            edit(&mut bytes);
+            bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+            Schematic::decode(&bytes, &mut BlockRegistry::default())
+        };
+        assert!(decode(&|_| ()).is_ok());
+        // 4 billion voxels per axis
+        let error = decode(&|bytes| bytes[6..18].fill(0xff)).unwrap_err();
+        assert!(matches!(error, SchematicError::Corrupt(_)), "{error}");
+        // a single run of 4 billion voxels
+        let runs = bytes.len() - 4 - RUN_LEN;
+        assert!(decode(&|bytes| bytes[runs..runs + 4].fill(0xff)).is_err());
+        assert!(decode(&|bytes| bytes[runs..runs + 4].fill(0)).is_err());
+        assert!(decode(&|bytes| bytes.truncate(bytes.len() - 1)).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::modules::math::noise::Noise;
+
+use super::{
+    block_registry::{Block, BlockRegistry},
+    chunk::Chunk,
+    scene::{ChunkIndex, Scene, WorldPos},
+    voxel::{Voxel, AIR},
+    world_generator::{Surface, WorldGenerator},
+};
+
+/// Trunk with a round canopy on top
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tree {
+    pub log: Voxel,
+    pub leaves: Voxel,
+    /// Trunk height range, inclusive
+    pub height: (isize, isize),
+    /// Canopy radius range, inclusive
+    pub canopy: (isize, isize),
+}
+
+impl Tree {
+    pub fn register(registry: &mut BlockRegistry) -> Self {
+        Self {
+            log: registry.register(Block::new("log", [102, 76, 51, 255])),
+            leaves: registry.register(Block::new("leaves", [58, 110, 42, 255])),
+            height: (4, 7),
+            canopy: (2, 3),
+        }
+    }
+
+    /// Furthest a voxel can be from the trunk base horizontally
+    pub fn reach(&self) -> isize {
+        self.canopy.1
+    }
+
+    /// Highest a voxel can be above the trunk base
+    pub fn top(&self) -> isize {
+        self.height.1 + self.canopy.1
+    }
+
+    /// Voxels of the tree growing out of the surface voxel `base`, trunk first.
+    /// `hash` picks the shape
+    pub fn voxels(&self, base: WorldPos, hash: u64) -> Vec<(WorldPos, Voxel)> {
+        let pick =
+            |(min, max): (isize, isize), hash: u64| min + (hash % (max - min + 1) as u64) as isize;
+        let height = pick(self.height, hash);
+        let radius = pick(self.canopy, hash >> 16);
+
+        let mut voxels: Vec<_> = (1..=height)
+            .map(|z| ([base[0], base[1], base[2] + z], self.log))
+            .collect();
+        let center = [base[0], base[1], base[2] + height];
+        for dz in -radius..=radius {
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    if dx * dx + dy * dy + dz * dz <= radius * radius + 1 {
+                        let pos = [center[0] + dx, center[1] + dy, center[2] + dz];
+                        voxels.push((pos, self.leaves));
+                    }
+                }
+            }
+        }
+        voxels
+    }
+}
+
+/// Adds trees to the chunks of another generator.
+/// Trees are chosen per column from the seed alone, every chunk places the parts
+/// of all trees that reach into it, so the result does not depend on the order
+/// chunks are generated in
+pub struct Decorated<G> {
+    generator: G,
+    tree: Tree,
+    noise: Noise,
+}
+
+impl<G: WorldGenerator + Surface> Decorated<G> {
+    pub fn new(seed: u64, generator: G, tree: Tree) -> Self {
+        Self {
+            generator,
+            tree,
+            noise: Noise::new(seed).derive(32),
+        }
+    }
+
+    /// Surface position of the tree growing on the column
+    pub fn tree_at(&self, column: [isize; 2]) -> Option<WorldPos> {
+        let hash = self.noise.hash([column[0] as i64, column[1] as i64, 0]);
+        // uniform in 0..1
+        let chance = (hash >> 40) as f32 / (1u64 << 24) as f32;
+        if chance >= self.generator.decoration_density(column) {
+            return None;
+        }
+        let z = self.generator.surface(column)?;
+        Some([column[0], column[1], z])
+    }
+}
+
+impl<G: WorldGenerator + Surface> WorldGenerator for Decorated<G> {
+    fn generate(&self, idx: ChunkIndex) -> Option<Chunk> {
+        const D: isize = Chunk::DIMENSIONS as isize;
+        let origin = idx.map(|c| c * D);
+        let reach = self.tree.reach();
+        let mut chunk = self.generator.generate(idx);
+
+        // columns are walked in the same order for every chunk, so overlapping
+        // trees resolve the same way on both sides of a border
+        for y in origin[1] - reach..origin[1] + D + reach {
+            for x in origin[0] - reach..origin[0] + D + reach {
+                let Some(base) = self.tree_at([x, y]) else {
+                    continue;
+                };
+                if base[2] + self.tree.top() < origin[2] || base[2] >= origin[2] + D {
+                    continue;
+                }
+                let hash = self.noise.hash([x as i64, y as i64, 1]);
+                for (pos, voxel) in self.tree.voxels(base, hash) {
+                    let (voxel_idx, local) = Scene::locate(pos);
+                    if voxel_idx != idx {
+                        continue;
+                    }
+                    let chunk = chunk.get_or_insert_with(Chunk::empty);
+                    let current = chunk.get(local);
+                    // leaves never replace anything, logs grow through leaves
+                    if current == AIR || (current == self.tree.leaves && voxel == self.tree.log) {
+                        chunk.set(local, voxel);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod structures_tests {
+    use crate::modules::logic::{
+        block_registry::BlockRegistry,
+        chunk::Chunk,
+        scene::{ChunkIndex, Scene},
+        test_utils::for_each_local,
+        voxel::Voxel,
+        world_generator::{Surface, WorldGenerator},
+    };
+
+    use super::{Decorated, Tree};
+
+    const D: isize = Chunk::DIMENSIONS as isize;
+    /// After the log and leaves of `Tree::register`
+    const GROUND: Voxel = 3;
+    const HEIGHT: isize = 20;
+
+    /// Flat ground with trees everywhere it is allowed
+    struct Flat;
+
+    impl WorldGenerator for Flat {
+        fn generate(&self, idx: ChunkIndex) -> Option<Chunk> {
+            match idx[2] * D {
+                z if z > HEIGHT => None,
+                z if z + D <= HEIGHT => Some(Chunk::filled(GROUND)),
+                z => {
+                    let mut chunk = Chunk::empty();
+                    for_each_local(|[x, y, local]| {
+                        if z + local as isize <= HEIGHT {
+                            chunk.set([x, y, local], GROUND);
+                        }
+                    });
+                    Some(chunk)
+                }
+            }
+        }
+    }
+
+    impl Surface for Flat {
+        fn surface(&self, _: [isize; 2]) -> Option<isize> {
+            Some(HEIGHT)
+        }
+
+        fn decoration_density(&self, [x, _]: [isize; 2]) -> f32 {
+            // only in a strip along the border between chunk x 0 and 1
+            if (D - 3..D + 3).contains(&x) {
+                0.05
+            } else {
+                0.0
+            }
+        }
+    }
+
+    fn decorated() -> (Decorated<Flat>, Tree) {
+        let tree = Tree::register(&mut BlockRegistry::default());
+        (Decorated::new(11, Flat, tree), tree)
+    }
+
+    #[test]
+    fn test_tree_shape() {
+        let tree = Tree::register(&mut BlockRegistry::default());
+        for hash in [0, 1, 12345, u64::MAX] {
+            let voxels = tree.voxels([0, 0, 0], hash);
+            assert_eq!(([0, 0, 1], tree.log), voxels[0]);
+            for (pos, _) in voxels {
+                assert!(pos[0].abs() <= tree.reach() && pos[1].abs() <= tree.reach());
+                assert!((1..=tree.top()).contains(&pos[2]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_across_borders() {
+        let (generator, tree) = decorated();
+        let chunks = [[0, 0, 0], [1, 0, 0]];
+        let mut scene = Scene::new(BlockRegistry::default());
+        for idx in chunks {
+            scene.insert_chunk(idx, generator.generate(idx).unwrap());
+        }
+
+        let mut crossing = 0;
+        // trees near the other borders also reach into unloaded chunks
+        for y in tree.reach()..D - tree.reach() {
+            for x in D - 3..D + 3 {
+                let Some(base) = generator.tree_at([x, y]) else {
+                    continue;
+                };
+                assert_eq!(tree.log, scene.get_voxel([x, y, base[2] + 1]));
+                let hash = generator.noise.hash([x as i64, y as i64, 1]);
+                let voxels = tree.voxels(base, hash);
+                // the whole tree is there, parts may belong to other trees
+                for (pos, _) in &voxels {
+                    assert!([tree.log, tree.leaves].contains(&scene.get_voxel(*pos)));
+                }
+                let sides: Vec<_> = voxels.iter().map(|(pos, _)| pos[0] < D).collect();
+                crossing += (sides.contains(&true) && sides.contains(&false)) as usize;
+            }
+        }
+        assert!(crossing > 0);
+    }
+
+    #[test]
+    fn test_order_independent() {
+        let (generator, _) = decorated();
+        let chunks = [[0, 0, 0], [1, 0, 0], [0, -1, 0], [1, -1, 0]];
+        let world = |order: &[ChunkIndex]| {
+            let mut scene = Scene::new(BlockRegistry::default());
+            for idx in order {
+                scene.insert_chunk(*idx, generator.generate(*idx).unwrap());
+            }
+            scene
+        };
+        let forward = world(&chunks);
+        let mut reversed = chunks;
+        reversed.reverse();
+        let backward = world(&reversed);
+        for idx in chunks {
+            let a = forward.get_chunk(idx).unwrap();
+            let b = backward.get_chunk(idx).unwrap();
+            for_each_local(|pos| assert_eq!(a.get(pos), b.get(pos)));
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use super::chunk::Chunk;
+
+/// Every position inside a chunk, x changes fastest
+pub fn for_each_local(mut f: impl FnMut([usize; 3])) {
+    const D: usize = Chunk::DIMENSIONS;
+    for z in 0..D {
+        for y in 0..D {
+            for x in 0..D {
+                f([x, y, z]);
+            }
+        }
+    }
+}
+
+/// FNV-1a over every voxel, 0 for missing chunks
+pub fn hash_chunk(chunk: Option<&Chunk>) -> u64 {
+    let Some(chunk) = chunk else {
+        return 0;
+    };
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for_each_local(|pos| {
+        for byte in chunk.get(pos).to_le_bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    });
+    hash
+}
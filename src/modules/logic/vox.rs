@@ -0,0 +1,619 @@
+use std::{collections::HashMap, fmt};
+
+use super::{
+    scene::{Scene, WorldPos},
+    voxel::{Color, Voxel, AIR},
+};
+
+/// Models and palette of a MagicaVoxel `.vox` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// Indexed by color index, index 0 is unused
+    pub palette: [Color; 256],
+    /// Models placed by the scene graph, every model once at the origin without one
+    pub instances: Vec<VoxInstance>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    /// Position and color index
+    pub voxels: Vec<([u8; 3], u8)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxInstance {
+    pub model: usize,
+    /// Position of the model center, as MagicaVoxel stores it
+    pub translation: [i32; 3],
+}
+
+impl VoxInstance {
+    /// Position of the voxel `[0, 0, 0]` of the model
+    pub fn min_corner(&self, model: &VoxModel) -> [i32; 3] {
+        [0, 1, 2].map(|i| self.translation[i] - (model.size[i] / 2) as i32)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxError(pub String);
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid vox file: {}", self.0)
+    }
+}
+
+fn invalid<T>(reason: impl Into<String>) -> Result<T, VoxError> {
+    Err(VoxError(reason.into()))
+}
+
+/// Palette used by files without an RGBA chunk
+pub fn default_palette() -> [Color; 256] {
+    let mut palette = [[0; 4]; 256];
+    // a 6x6x6 color cube without black, blue changing fastest
+    for (i, color) in palette[1..216].iter_mut().enumerate() {
+        let level = |n: usize| 0xff - 0x33 * (n % 6) as u8;
+        *color = [level(i / 36), level(i / 6), level(i), 0xff];
+    }
+    // ramps of red, green, blue and gray
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for (i, level) in RAMP.iter().enumerate() {
+        palette[216 + i] = [*level, 0, 0, 0xff];
+        palette[226 + i] = [0, *level, 0, 0xff];
+        palette[236 + i] = [0, 0, *level, 0xff];
+        palette[246 + i] = [*level, *level, *level, 0xff];
+    }
+    palette
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], VoxError> {
+        if self.bytes.len() < n {
+            return invalid("unexpected end of data");
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn i32(&mut self) -> Result<i32, VoxError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, VoxError> {
+        usize::try_from(self.i32()?).or_else(|_| invalid("negative length"))
+    }
+
+    fn string(&mut self) -> Result<String, VoxError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).or_else(|_| invalid("string is not utf-8"))
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>, VoxError> {
+        (0..self.len()?)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect()
+    }
+}
+
+enum Node {
+    Transform { child: i32, translation: [i32; 3] },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<usize> },
+}
+
+pub fn parse(bytes: &[u8]) -> Result<VoxFile, VoxError> {
+    let mut reader = Reader { bytes };
+    if reader.take(4)? != b"VOX " {
+        return invalid("missing `VOX ` magic");
+    }
+    reader.i32()?;
+    if reader.take(4)? != b"MAIN" {
+        return invalid("missing MAIN chunk");
+    }
+    let content = reader.len()?;
+    reader.take(content)?;
+    let children = reader.len()?;
+    let mut reader = Reader {
+        bytes: reader.take(children)?,
+    };
+
+    let mut models = Vec::new();
+    let mut size = None;
+    let mut palette = default_palette();
+    let mut nodes = HashMap::new();
+    while !reader.bytes.is_empty() {
+        let id: [u8; 4] = reader.take(4)?.try_into().unwrap();
+        let content = reader.len()?;
+        let children = reader.len()?;
+        let mut chunk = Reader {
+            bytes: reader.take(content)?,
+        };
+        reader.take(children)?;
+        match &id {
+            b"SIZE" => {
+                let [x, y, z] = [chunk.i32()?, chunk.i32()?, chunk.i32()?];
+                if [x, y, z].iter().any(|c| !(1..=256).contains(c)) {
+                    return invalid(format!("model size {x}x{y}x{z} out of range"));
+                }
+                size = Some([x, y, z].map(|c| c as u32));
+            }
+            b"XYZI" => {
+                let Some(size) = size.take() else {
+                    return invalid("XYZI chunk without SIZE chunk");
+                };
+                let voxels = (0..chunk.len()?)
+                    .map(|_| {
+                        let [x, y, z, color] = chunk.take(4)?.try_into().unwrap();
+                        if [x, y, z].iter().zip(size).any(|(c, s)| *c as u32 >= s) {
+                            return invalid("voxel outside of its model");
+                        }
+                        Ok(([x, y, z], color))
+                    })
+                    .collect::<Result<_, _>>()?;
+                models.push(VoxModel { size, voxels });
+            }
+            b"RGBA" => {
+                // color index i is stored at i - 1
+                for color in &mut palette[1..] {
+                    *color = chunk.take(4)?.try_into().unwrap();
+                }
+            }
+            b"nTRN" => {
+                let id = chunk.i32()?;
+                chunk.dict()?;
+                let child = chunk.i32()?;
+                chunk.take(8)?;
+                let frames = chunk.len()?;
+                let mut translation = [0; 3];
+                if frames > 0 {
+                    if let Some(t) = chunk.dict()?.get("_t") {
+                        let values: Vec<_> = t.split_whitespace().map(str::parse).collect();
+                        match values[..] {
+                            [Ok(x), Ok(y), Ok(z)] => translation = [x, y, z],
+                            _ => return invalid(format!("bad translation `{t}`")),
+                        }
+                    }
+                }
+                nodes.insert(id, Node::Transform { child, translation });
+            }
+            b"nGRP" => {
+                let id = chunk.i32()?;
+                chunk.dict()?;
+                let children = (0..chunk.len()?)
+                    .map(|_| chunk.i32())
+                    .collect::<Result<_, _>>()?;
+                nodes.insert(id, Node::Group { children });
+            }
+            b"nSHP" => {
+                let id = chunk.i32()?;
+                chunk.dict()?;
+                let models = (0..chunk.len()?)
+                    .map(|_| {
+                        let model = chunk.len()?;
+                        chunk.dict()?;
+                        Ok(model)
+                    })
+                    .collect::<Result<_, _>>()?;
+                nodes.insert(id, Node::Shape { models });
+            }
+            // PACK, materials, layers, cameras and so on
+            _ => (),
+        }
+    }
+
+    let instances = if nodes.is_empty() {
+        (0..models.len())
+            .map(|model| VoxInstance {
+                model,
+                translation: models[model].size.map(|c| (c / 2) as i32),
+            })
+            .collect()
+    } else {
+        let mut instances = Vec::new();
+        collect_instances(&nodes, 0, [0; 3], 0, &mut instances)?;
+        instances
+    };
+    if let Some(instance) = instances.iter().find(|i| i.model >= models.len()) {
+        return invalid(format!("shape uses missing model {}", instance.model));
+    }
+    Ok(VoxFile {
+        models,
+        palette,
+        instances,
+    })
+}
+
+fn collect_instances(
+    nodes: &HashMap<i32, Node>,
+    id: i32,
+    translation: [i32; 3],
+    depth: usize,
+    instances: &mut Vec<VoxInstance>,
+) -> Result<(), VoxError> {
+    // a cycle would recurse forever
+    if depth > 64 {
+        return invalid("scene graph is too deep");
+    }
+    match nodes.get(&id) {
+        None => invalid(format!("missing scene node {id}")),
+        Some(Node::Transform {
+            child,
+            translation: t,
+        }) => {
+            let translation = [0, 1, 2].map(|i| translation[i] + t[i]);
+            collect_instances(nodes, *child, translation, depth + 1, instances)
+        }
+        Some(Node::Group { children }) => children.iter().try_for_each(|child| {
+            collect_instances(nodes, *child, translation, depth + 1, instances)
+        }),
+        Some(Node::Shape { models }) => {
+            instances.extend(models.iter().map(|model| VoxInstance {
+                model: *model,
+                translation,
+            }));
+            Ok(())
+        }
+    }
+}
+
+impl VoxFile {
+    /// Writes every instance into the scene, the origin of the file lands on `offset`.
+    /// Colors are registered as blocks, returns the number of voxels placed
+    pub fn place(&self, scene: &mut Scene, offset: WorldPos) -> usize {
+        let mut blocks: [Option<Voxel>; 256] = [None; 256];
+        let mut placed = 0;
+        for instance in &self.instances {
+            let model = &self.models[instance.model];
+            let corner = instance.min_corner(model);
+            for (pos, color) in &model.voxels {
+                let voxel = *blocks[*color as usize].get_or_insert_with(|| {
+                    scene
+                        .registry_mut()
+                        .register_color(self.palette[*color as usize])
+                });
+                let world = [0, 1, 2].map(|i| offset[i] + (corner[i] + pos[i] as i32) as isize);
+                scene.set_voxel(world, voxel);
+                placed += 1;
+            }
+        }
+        placed
+    }
+}
+
+/// Largest model MagicaVoxel accepts along every axis
+pub const MODEL_SIZE: usize = 256;
+
+impl VoxFile {
+    /// Copies the voxels in `min..max` into models of at most `MODEL_SIZE`,
+    /// placed so that importing at `min` reproduces them. Past 255 distinct
+    /// colors, voxels get the closest palette color
+    pub fn from_region(scene: &Scene, min: WorldPos, max: WorldPos) -> Self {
+        let mut palette = [[0; 4]; 256];
+        let mut colors = 0;
+        let mut indices: HashMap<Voxel, u8> = HashMap::new();
+        let mut models = Vec::new();
+        let mut instances = Vec::new();
+
+        let steps = |axis: usize| (min[axis]..max[axis]).step_by(MODEL_SIZE);
+        for z in steps(2) {
+            for y in steps(1) {
+                for x in steps(0) {
+                    let corner = [x, y, z];
+                    let size = [0, 1, 2].map(|i| (max[i] - corner[i]).min(MODEL_SIZE as isize));
+                    let mut voxels = Vec::new();
+                    for_each_pos(size, |pos| {
+                        let world = [0, 1, 2].map(|i| corner[i] + pos[i] as isize);
+                        let voxel = scene.get_voxel(world);
+                        if voxel == AIR {
+                            return;
+                        }
+                        let index = *indices.entry(voxel).or_insert_with(|| {
+                            let color = scene.registry()[voxel].color;
+                            if colors < 255 {
+                                colors += 1;
+                                palette[colors] = color;
+                                colors as u8
+                            } else {
+                                closest_color(&palette, color)
+                            }
+                        });
+                        voxels.push((pos, index));
+                    });
+                    if voxels.is_empty() {
+                        continue;
+                    }
+                    let size = size.map(|c| c as u32);
+                    let offset = [0, 1, 2].map(|i| (corner[i] - min[i]) as i32);
+                    instances.push(VoxInstance {
+                        model: models.len(),
+                        translation: [0, 1, 2].map(|i| offset[i] + (size[i] / 2) as i32),
+                    });
+                    models.push(VoxModel { size, voxels });
+                }
+            }
+        }
+        Self {
+            models,
+            palette,
+            instances,
+        }
+    }
+
+    /// Encodes the file, instances are written as a flat scene graph
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut children = Vec::new();
+        for model in &self.models {
+            let size = model.size.map(|c| c as i32);
+            write_chunk(&mut children, b"SIZE", &ints(&size));
+            let mut xyzi = ints(&[model.voxels.len() as i32]);
+            for ([x, y, z], color) in &model.voxels {
+                xyzi.extend([*x, *y, *z, *color]);
+            }
+            write_chunk(&mut children, b"XYZI", &xyzi);
+        }
+
+        // root transform, a group and a transform with a shape per instance
+        write_chunk(&mut children, b"nTRN", &transform(0, 1, [0; 3]));
+        let mut group = ints(&[1, 0, self.instances.len() as i32]);
+        for i in 0..self.instances.len() as i32 {
+            group.extend(ints(&[2 + 2 * i]));
+        }
+        write_chunk(&mut children, b"nGRP", &group);
+        for (i, instance) in self.instances.iter().enumerate() {
+            let id = 2 + 2 * i as i32;
+            write_chunk(
+                &mut children,
+                b"nTRN",
+                &transform(id, id + 1, instance.translation),
+            );
+            let shape = ints(&[id + 1, 0, 1, instance.model as i32, 0]);
+            write_chunk(&mut children, b"nSHP", &shape);
+        }
+
+        let rgba: Vec<u8> = self.palette[1..]
+            .iter()
+            .flatten()
+            .copied()
+            .chain([0; 4])
+            .collect();
+        write_chunk(&mut children, b"RGBA", &rgba);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(ints(&[150]));
+        bytes.extend(b"MAIN");
+        bytes.extend(ints(&[0, children.len() as i32]));
+        bytes.extend(children);
+        bytes
+    }
+}
+
+fn for_each_pos(size: [isize; 3], mut f: impl FnMut([u8; 3])) {
+    for z in 0..size[2] {
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                f([x, y, z].map(|c| c as u8));
+            }
+        }
+    }
+}
+
+/// Palette index, not 0, whose color is nearest to `color`
+fn closest_color(palette: &[Color; 256], color: Color) -> u8 {
+    let distance = |other: &Color| -> u32 {
+        (0..4)
+            .map(|i| (other[i] as i32 - color[i] as i32).pow(2) as u32)
+            .sum()
+    };
+    (1..256).min_by_key(|&i| distance(&palette[i])).unwrap() as u8
+}
+
+fn ints(values: &[i32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend(id);
+    bytes.extend(ints(&[content.len() as i32, 0]));
+    bytes.extend(content);
+}
+
+/// Transform node with a single frame
+fn transform(id: i32, child: i32, [x, y, z]: [i32; 3]) -> Vec<u8> {
+    let value = format!("{x} {y} {z}");
+    let mut content = ints(&[id, 0, child, -1, -1, 1, 1, 2]);
+    content.extend(b"_t");
+    content.extend(ints(&[value.len() as i32]));
+    content.extend(value.as_bytes());
+    content
+}
+
+#[cfg(test)]
+mod vox_tests {
+    use crate::modules::logic::{block_registry::BlockRegistry, scene::Scene, voxel::AIR};
+
+    use super::{default_palette, ints, parse, VoxFile, MODEL_SIZE};
+
+    /// One red voxel in a 2x1x1 model, default palette
+    const SINGLE: &[u8] = &[
+        b'V', b'O', b'X', b' ', 150, 0, 0, 0, //
+        b'M', b'A', b'I', b'N', 0, 0, 0, 0, 44, 0, 0, 0, //
+        b'S', b'I', b'Z', b'E', 12, 0, 0, 0, 0, 0, 0, 0, //
+        2, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, //
+        b'X', b'Y', b'Z', b'I', 8, 0, 0, 0, 0, 0, 0, 0, //
+        1, 0, 0, 0, 1, 0, 0, 216, //
+    ];
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes.extend_from_slice(children);
+        bytes
+    }
+
+    fn file(children: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&200i32.to_le_bytes());
+        bytes.extend(chunk(b"MAIN", &[], &children.concat()));
+        bytes
+    }
+
+    /// Dictionary with a single entry
+    fn dict(key: &str, value: &str) -> Vec<u8> {
+        let mut bytes = ints(&[1, key.len() as i32]);
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.extend(ints(&[value.len() as i32]));
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    fn transform(id: i32, child: i32, translation: &str) -> Vec<u8> {
+        let mut content = ints(&[id, 0, child, -1, 0, 1]);
+        content.extend(dict("_t", translation));
+        chunk(b"nTRN", &content, &[])
+    }
+
+    #[test]
+    fn test_default_palette() {
+        let palette = default_palette();
+        assert_eq!([0xff, 0xff, 0xff, 0xff], palette[1]);
+        assert_eq!([0xff, 0xff, 0xcc, 0xff], palette[2]);
+        assert_eq!([0xff, 0xcc, 0xff, 0xff], palette[7]);
+        assert_eq!([0x00, 0x00, 0x33, 0xff], palette[215]);
+        assert_eq!([0xee, 0x00, 0x00, 0xff], palette[216]);
+        assert_eq!([0x11, 0x11, 0x11, 0xff], palette[255]);
+    }
+
+    #[test]
+    fn test_single() {
+        let vox = parse(SINGLE).unwrap();
+        assert_eq!(1, vox.models.len());
+        assert_eq!(vec![([1, 0, 0], 216)], vox.models[0].voxels);
+
+        let mut scene = Scene::new(BlockRegistry::default());
+        assert_eq!(1, vox.place(&mut scene, [-1, 40, 0]));
+        let voxel = scene.get_voxel([0, 40, 0]);
+        assert_eq!([0xee, 0, 0, 0xff], scene.registry()[voxel].color);
+        assert_eq!(AIR, scene.get_voxel([-1, 40, 0]));
+    }
+
+    #[test]
+    fn test_models_and_palette() {
+        let mut rgba = vec![0; 256 * 4];
+        rgba[..8].copy_from_slice(&[10, 20, 30, 255, 40, 50, 60, 255]);
+        let model = |size: [i32; 3], voxels: &[[u8; 4]]| {
+            let mut xyzi = ints(&[voxels.len() as i32]);
+            xyzi.extend(voxels.concat());
+            [
+                chunk(b"SIZE", &ints(&size), &[]),
+                chunk(b"XYZI", &xyzi, &[]),
+            ]
+        };
+        let [size_a, xyzi_a] = model([40, 2, 2], &[[0, 0, 0, 1], [39, 1, 1, 2]]);
+        let [size_b, xyzi_b] = model([2, 2, 2], &[[1, 1, 1, 1]]);
+        let group = ints(&[1, 0, 2, 2, 4]);
+        let shape = |id: i32, model: i32| chunk(b"nSHP", &ints(&[id, 0, 1, model, 0]), &[]);
+        let bytes = file(&[
+            size_a,
+            xyzi_a,
+            size_b,
+            xyzi_b,
+            chunk(b"RGBA", &rgba, &[]),
+            transform(0, 1, "0 0 0"),
+            chunk(b"nGRP", &group, &[]),
+            transform(2, 3, "20 1 -7"),
+            shape(3, 0),
+            transform(4, 5, "-1 -1 -1"),
+            shape(5, 1),
+            chunk(b"LAYR", &ints(&[0, 0, -1]), &[]),
+        ]);
+
+        let vox = parse(&bytes).unwrap();
+        assert_eq!(2, vox.models.len());
+        assert_eq!(2, vox.instances.len());
+        assert_eq!([10, 20, 30, 255], vox.palette[1]);
+
+        let mut scene = Scene::new(BlockRegistry::default());
+        assert_eq!(3, vox.place(&mut scene, [0, 0, 0]));
+        // model a spans x 0..40 around its center at 20, crossing a chunk border
+        let a = scene.get_voxel([0, 0, -8]);
+        let b = scene.get_voxel([39, 1, -7]);
+        assert_eq!([10, 20, 30, 255], scene.registry()[a].color);
+        assert_eq!([40, 50, 60, 255], scene.registry()[b].color);
+        assert_eq!(a, scene.get_voxel([-1, -1, -1]));
+        assert!(scene.get_chunk([0, 0, -1]).is_some());
+        assert!(scene.get_chunk([1, 0, -1]).is_some());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse(b"RIFF").is_err());
+        assert!(parse(&SINGLE[..SINGLE.len() - 2]).is_err());
+        let mut outside = SINGLE.to_vec();
+        outside[SINGLE.len() - 3] = 1;
+        assert!(parse(&outside).is_err());
+        let missing = file(&[transform(0, 9, "0 0 0")]);
+        assert!(parse(&missing).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut registry = BlockRegistry::default();
+        let red = registry.register_color([200, 0, 0, 255]);
+        let blue = registry.register_color([0, 0, 200, 255]);
+        let mut scene = Scene::new(registry);
+        let min = [-20, 5, -3];
+        // longer than a model along x
+        let max = [min[0] + MODEL_SIZE as isize + 10, 9, 2];
+        let voxels = [
+            ([-20, 5, -3], red),
+            ([-1, 6, 0], blue),
+            ([0, 8, 1], red),
+            ([min[0] + MODEL_SIZE as isize + 3, 7, -2], blue),
+        ];
+        for (pos, voxel) in voxels {
+            scene.set_voxel(pos, voxel);
+        }
+        // outside of the region
+        scene.set_voxel([0, 9, 0], red);
+
+        let vox = VoxFile::from_region(&scene, min, max);
+        assert_eq!(2, vox.models.len());
+        let parsed = parse(&vox.to_bytes()).unwrap();
+        assert_eq!(vox, parsed);
+
+        let mut copy = Scene::new(BlockRegistry::default());
+        let offset = [100, -40, 7];
+        assert_eq!(voxels.len(), parsed.place(&mut copy, offset));
+        for (pos, voxel) in voxels {
+            let moved = [0, 1, 2].map(|i| pos[i] - min[i] + offset[i]);
+            let color = scene.registry()[voxel].color;
+            assert_eq!(color, copy.registry()[copy.get_voxel(moved)].color);
+        }
+    }
+
+    #[test]
+    fn test_palette_overflow() {
+        let mut registry = BlockRegistry::default();
+        let mut scene = Scene::new(BlockRegistry::default());
+        for i in 0..300 {
+            let voxel = registry.register_color([(i % 256) as u8, (i / 256) as u8, 0, 255]);
+            *scene.registry_mut() = registry.clone();
+            scene.set_voxel([i % 20, i / 20, 0], voxel);
+        }
+        let vox = VoxFile::from_region(&scene, [0, 0, 0], [20, 15, 1]);
+        let voxels = &vox.models[0].voxels;
+        assert_eq!(300, voxels.len());
+        // the last colors fall back to the closest ones in the palette
+        assert_eq!([43, 0, 0, 255], vox.palette[voxels[299].1 as usize]);
+        assert_eq!(255, vox.palette[1..].iter().filter(|c| c[3] == 255).count());
+    }
+}
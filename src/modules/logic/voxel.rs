@@ -1,2 +1,4 @@
 pub type Color = [u8; 4];
-pub type Voxel = Option<Color>;
+/// Block id in the `BlockRegistry`
+pub type Voxel = u16;
+pub const AIR: Voxel = 0;
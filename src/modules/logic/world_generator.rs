@@ -0,0 +1,213 @@
+use crate::modules::math::noise::{Fractal, Noise};
+
+use super::{
+    block_registry::{Block, BlockRegistry},
+    chunk::Chunk,
+    scene::ChunkIndex,
+    voxel::Voxel,
+};
+
+/// Creates the initial content of chunks.
+/// The same chunk index must always generate the same chunk
+pub trait WorldGenerator {
+    /// `None` if the chunk is all air
+    fn generate(&self, idx: ChunkIndex) -> Option<Chunk>;
+}
+
+/// Generators whose ground can be decorated
+pub trait Surface {
+    /// Z of the topmost solid voxel of the column, `None` if nothing should stand on it
+    fn surface(&self, column: [isize; 2]) -> Option<isize>;
+
+    /// Chance of a decoration on the column
+    fn decoration_density(&self, column: [isize; 2]) -> f32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightmapSettings {
+    /// World z of the terrain surface where the noise is 0
+    pub base_height: f32,
+    /// Maximal distance of the surface from `base_height`
+    pub amplitude: f32,
+    pub fractal: Fractal,
+    /// Blocks of dirt below the grass
+    pub dirt_depth: isize,
+}
+
+impl Default for HeightmapSettings {
+    fn default() -> Self {
+        Self {
+            base_height: 0.0,
+            amplitude: 24.0,
+            fractal: Fractal {
+                frequency: 1.0 / 128.0,
+                ..Default::default()
+            },
+            dirt_depth: 3,
+        }
+    }
+}
+
+/// Blocks of the ground from the top down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerrainBlocks {
+    pub surface: Voxel,
+    pub filler: Voxel,
+    /// Blocks of filler below the surface
+    pub filler_depth: isize,
+    pub stone: Voxel,
+}
+
+impl TerrainBlocks {
+    /// Grass on dirt on stone
+    pub fn register(registry: &mut BlockRegistry, filler_depth: isize) -> Self {
+        Self {
+            surface: registry.register(Block::new("grass", [95, 159, 53, 255])),
+            filler: registry.register(Block::new("dirt", [134, 96, 67, 255])),
+            filler_depth,
+            stone: registry.register(Block::new("stone", [125, 125, 125, 255])),
+        }
+    }
+
+    /// `depth` is the number of solid voxels above
+    pub fn layer(&self, depth: isize) -> Voxel {
+        match depth {
+            0 => self.surface,
+            depth if depth <= self.filler_depth => self.filler,
+            _ => self.stone,
+        }
+    }
+}
+
+/// Rolling hills of stone, covered by dirt and grass
+pub struct HeightmapGenerator {
+    noise: Noise,
+    settings: HeightmapSettings,
+    blocks: TerrainBlocks,
+}
+
+impl HeightmapGenerator {
+    pub fn new(seed: u64, settings: HeightmapSettings, registry: &mut BlockRegistry) -> Self {
+        Self {
+            noise: Noise::new(seed),
+            settings,
+            blocks: TerrainBlocks::register(registry, settings.dirt_depth),
+        }
+    }
+
+    /// Z of the topmost solid voxel of the column
+    pub fn height(&self, [x, y]: [isize; 2]) -> isize {
+        let HeightmapSettings {
+            base_height,
+            amplitude,
+            fractal,
+            ..
+        } = self.settings;
+        let noise = self.noise.fractal2([x as f32, y as f32], fractal);
+        (base_height + amplitude * noise).floor() as isize
+    }
+
+    /// Voxel at world height `z` of a column whose surface is at `height`
+    fn layer(&self, z: isize, height: isize) -> Option<Voxel> {
+        let depth = height - z;
+        (depth >= 0).then(|| self.blocks.layer(depth))
+    }
+}
+
+impl WorldGenerator for HeightmapGenerator {
+    fn generate(&self, idx: ChunkIndex) -> Option<Chunk> {
+        const D: isize = Chunk::DIMENSIONS as isize;
+        let origin = idx.map(|c| c * D);
+        let lowest = (self.settings.base_height - self.settings.amplitude).floor() as isize;
+        let highest = (self.settings.base_height + self.settings.amplitude).ceil() as isize;
+        if origin[2] > highest {
+            return None;
+        }
+        if origin[2] + D - 1 < lowest - self.settings.dirt_depth {
+            return Some(Chunk::filled(self.blocks.stone));
+        }
+
+        let mut chunk = Chunk::empty();
+        for y in 0..D {
+            for x in 0..D {
+                let height = self.height([origin[0] + x, origin[1] + y]);
+                for z in 0..D {
+                    if let Some(voxel) = self.layer(origin[2] + z, height) {
+                        chunk.set([x, y, z].map(|c| c as usize), voxel);
+                    }
+                }
+            }
+        }
+        (!chunk.is_empty()).then_some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod world_generator_tests {
+    use crate::modules::logic::{
+        block_registry::BlockRegistry, chunk::Chunk, scene::ChunkIndex, test_utils::hash_chunk,
+        voxel::AIR,
+    };
+
+    use super::{HeightmapGenerator, HeightmapSettings, WorldGenerator};
+
+    const D: usize = Chunk::DIMENSIONS;
+
+    fn generator(seed: u64) -> HeightmapGenerator {
+        HeightmapGenerator::new(
+            seed,
+            HeightmapSettings::default(),
+            &mut BlockRegistry::default(),
+        )
+    }
+
+    const SAMPLES: [ChunkIndex; 4] = [[0, 0, 0], [0, 0, -1], [-3, 5, 0], [7, -2, -1]];
+
+    #[test]
+    fn test_regression() {
+        let generator = generator(1234);
+        let hashes = SAMPLES.map(|idx| hash_chunk(generator.generate(idx).as_ref()));
+        let expected = [
+            0x10e5_131b_af07_06e5,
+            0x5c2e_e8cc_a45a_d686,
+            0xa71c_db0a_1cb8_0ce6,
+            0xa547_25fc_1ef1_9bb5,
+        ];
+        assert_eq!(expected, hashes);
+    }
+
+    #[test]
+    fn test_seed() {
+        let hashes = |seed| SAMPLES.map(|idx| hash_chunk(generator(seed).generate(idx).as_ref()));
+        assert_eq!(hashes(5), hashes(5));
+        assert_ne!(hashes(5), hashes(6));
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut registry = BlockRegistry::default();
+        let generator = HeightmapGenerator::new(9, HeightmapSettings::default(), &mut registry);
+        let [grass, dirt, stone] = ["grass", "dirt", "stone"].map(|n| registry.id(n).unwrap());
+
+        assert!(generator.generate([0, 0, 5]).is_none());
+        assert_eq!(
+            stone,
+            generator.generate([0, 0, -5]).unwrap().get([3, 4, 5])
+        );
+
+        let column = |idx: ChunkIndex| generator.generate(idx).map(|c| c.get([7, 7, 0]));
+        let height = generator.height([7, 7]);
+        let chunk_z = height.div_euclid(D as isize);
+        let chunk = generator.generate([0, 0, chunk_z]).unwrap();
+        let local = height.rem_euclid(D as isize) as usize;
+        assert_eq!(grass, chunk.get([7, 7, local]));
+        if local + 1 < D {
+            assert_eq!(AIR, chunk.get([7, 7, local + 1]));
+        }
+        if local >= 4 {
+            assert_eq!(dirt, chunk.get([7, 7, local - 3]));
+            assert_eq!(stone, chunk.get([7, 7, local - 4]));
+        }
+        assert_eq!(Some(stone), column([0, 0, chunk_z - 1]));
+    }
+}
@@ -0,0 +1,215 @@
+/// Seeded gradient (Perlin) noise, deterministic on every platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Noise {
+    seed: u64,
+}
+
+/// Octaves of noise summed with growing frequency and shrinking amplitude
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fractal {
+    pub octaves: u32,
+    /// Frequency of the first octave
+    pub frequency: f32,
+    /// Frequency multiplier per octave
+    pub lacunarity: f32,
+    /// Amplitude multiplier per octave
+    pub gain: f32,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self {
+            octaves: 4,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+const GRADIENTS_2: [[f32; 2]; 8] = [
+    [1.0, 1.0],
+    [-1.0, 1.0],
+    [1.0, -1.0],
+    [-1.0, -1.0],
+    [1.0, 0.0],
+    [-1.0, 0.0],
+    [0.0, 1.0],
+    [0.0, -1.0],
+];
+
+/// Edge midpoints of a cube, padded to 16 so a hash can be masked
+const GRADIENTS_3: [[f32; 3]; 16] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [0.0, -1.0, 1.0],
+    [0.0, -1.0, -1.0],
+];
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Independent noise with the same seed, e.g. one per generated property
+    pub fn derive(&self, salt: u64) -> Self {
+        Self::new(mix(self.seed ^ mix(salt)))
+    }
+
+    /// Uniform hash of a lattice point
+    pub fn hash(&self, point: [i64; 3]) -> u64 {
+        point.iter().fold(self.seed, |h, &c| {
+            mix(h ^ (c as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        })
+    }
+
+    /// Roughly in -1..=1, zero on every integer point
+    pub fn perlin2(&self, [x, y]: [f32; 2]) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i64, y0 as i64);
+        let corner = |dx: i64, dy: i64| {
+            let g = GRADIENTS_2[(self.hash([ix + dx, iy + dy, 0]) & 7) as usize];
+            g[0] * (fx - dx as f32) + g[1] * (fy - dy as f32)
+        };
+        let (u, v) = (fade(fx), fade(fy));
+        let bottom = lerp(corner(0, 0), corner(1, 0), u);
+        let top = lerp(corner(0, 1), corner(1, 1), u);
+        lerp(bottom, top, v)
+    }
+
+    /// Roughly in -1..=1, zero on every integer point
+    pub fn perlin3(&self, [x, y, z]: [f32; 3]) -> f32 {
+        let floor = [x.floor(), y.floor(), z.floor()];
+        let f = [x - floor[0], y - floor[1], z - floor[2]];
+        let i = floor.map(|c| c as i64);
+        let corner = |d: [i64; 3]| {
+            let g = GRADIENTS_3[(self.hash([i[0] + d[0], i[1] + d[1], i[2] + d[2]]) & 15) as usize];
+            (0..3).map(|a| g[a] * (f[a] - d[a] as f32)).sum::<f32>()
+        };
+        let [u, v, w] = f.map(fade);
+        let lerp_x = |y, z| lerp(corner([0, y, z]), corner([1, y, z]), u);
+        let near = lerp(lerp_x(0, 0), lerp_x(1, 0), v);
+        let far = lerp(lerp_x(0, 1), lerp_x(1, 1), v);
+        lerp(near, far, w)
+    }
+
+    /// In -1..=1
+    pub fn fractal2(&self, pos: [f32; 2], fractal: Fractal) -> f32 {
+        fractal.sum(|i, frequency| self.derive(i as u64).perlin2(pos.map(|c| c * frequency)))
+    }
+
+    /// In -1..=1
+    pub fn fractal3(&self, pos: [f32; 3], fractal: Fractal) -> f32 {
+        fractal.sum(|i, frequency| self.derive(i as u64).perlin3(pos.map(|c| c * frequency)))
+    }
+}
+
+impl Fractal {
+    fn sum(&self, mut octave: impl FnMut(u32, f32) -> f32) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut max = 0.0;
+        for i in 0..self.octaves {
+            total += octave(i, frequency) * amplitude;
+            max += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        if max == 0.0 {
+            return 0.0;
+        }
+        (total / max).clamp(-1.0, 1.0)
+    }
+}
+
+/// splitmix64 finalizer
+fn mix(mut h: u64) -> u64 {
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+/// 6t^5 - 15t^4 + 10t^3, smooth first and second derivative at 0 and 1
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+//##########################//
+//######### TESTS ##########//
+//##########################//
+
+#[cfg(test)]
+mod noise_tests {
+    use crate::modules::math::noise::*;
+
+    fn samples() -> impl Iterator<Item = [f32; 3]> {
+        (0..2000).map(|i| {
+            let i = i as f32;
+            [i * 0.731 - 500.0, i * 0.377 + 12.5, i * -0.119]
+        })
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let a = Noise::new(42);
+        let b = Noise::new(42);
+        let c = Noise::new(43);
+        let mut differs = false;
+        for [x, y, z] in samples() {
+            assert_eq!(a.perlin2([x, y]), b.perlin2([x, y]));
+            assert_eq!(a.perlin3([x, y, z]), b.perlin3([x, y, z]));
+            differs |= a.perlin3([x, y, z]) != c.perlin3([x, y, z]);
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn test_range() {
+        let noise = Noise::new(7);
+        let (mut min, mut max) = (f32::MAX, f32::MIN);
+        for [x, y, z] in samples() {
+            for value in [
+                noise.perlin2([x, y]),
+                noise.perlin3([x, y, z]),
+                noise.fractal2([x, y], Fractal::default()),
+                noise.fractal3([x, y, z], Fractal::default()),
+            ] {
+                assert!((-1.0..=1.0).contains(&value), "{value}");
+                min = min.min(value);
+                max = max.max(value);
+            }
+        }
+        // not degenerate
+        assert!(min < -0.3 && max > 0.3);
+    }
+
+    #[test]
+    fn test_continuous() {
+        let noise = Noise::new(1);
+        assert_eq!(0.0, noise.perlin2([3.0, -8.0]));
+        assert_eq!(0.0, noise.perlin3([3.0, -8.0, 2.0]));
+        for [x, y, z] in samples() {
+            let step = 1e-3;
+            assert!((noise.perlin2([x, y]) - noise.perlin2([x + step, y])).abs() < 0.01);
+            assert!((noise.perlin3([x, y, z]) - noise.perlin3([x, y, z + step])).abs() < 0.01);
+        }
+    }
+}
@@ -4,6 +4,9 @@ use vulkano::{
     command_buffer::allocator::{
         StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
     },
+    descriptor_set::allocator::{
+        StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo,
+    },
     format::Format,
     image::{
         view::{ImageView, ImageViewCreateInfo},
@@ -22,6 +25,13 @@ impl Renderer {
         )
     }
 
+    pub fn create_descriptor_set_allocator(&self) -> StandardDescriptorSetAllocator {
+        StandardDescriptorSetAllocator::new(
+            self.device.clone(),
+            StandardDescriptorSetAllocatorCreateInfo::default(),
+        )
+    }
+
     pub fn create_memory_allocator(&self) -> StandardMemoryAllocator {
         StandardMemoryAllocator::new_default(self.device.clone())
     }
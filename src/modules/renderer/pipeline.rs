@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use vulkano::{
+    descriptor_set::layout::{DescriptorSetLayout, DescriptorSetLayoutCreateInfo},
     device::Device,
     pipeline::{
         graphics::GraphicsPipelineCreateInfo, layout::PipelineLayoutCreateInfo, GraphicsPipeline,
@@ -33,4 +34,11 @@ impl Renderer {
     pub fn pipeline_layout(&self, create_info: PipelineLayoutCreateInfo) -> Arc<PipelineLayout> {
         PipelineLayout::new(self.device.clone(), create_info).unwrap()
     }
+
+    pub fn descriptor_set_layout(
+        &self,
+        create_info: DescriptorSetLayoutCreateInfo,
+    ) -> Arc<DescriptorSetLayout> {
+        DescriptorSetLayout::new(self.device.clone(), create_info).unwrap()
+    }
 }
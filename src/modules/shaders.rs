@@ -17,3 +17,8 @@ pub mod voxel_vertex_shader {
     use vulkano_shaders::shader;
     shader!(ty: "vertex", path: "src/shaders/voxel_vertex.vert");
 }
+
+pub mod outline_vertex_shader {
+    use vulkano_shaders::shader;
+    shader!(ty: "vertex", path: "src/shaders/outline_vertex.vert");
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout (location = 0) in vec3 pos;
+
+layout (location = 0) out vec4 out_color;
+
+layout (push_constant) uniform Outline {
+    mat4 pvm;
+    vec4 color;
+};
+
+void main() {
+    gl_Position = pvm * vec4(pos, 1.0);
+    out_color = color;
+}
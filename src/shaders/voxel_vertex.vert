@@ -1,18 +1,46 @@
 #version 450
 
-layout (location = 0) in vec3 pos;
+// x, y, z by 6 bits each, 3 bits of face id, 2 bits of ambient occlusion
+layout (location = 0) in uint data;
 
-layout (location = 1) in vec4 color;
+// index into block_colors
+layout (location = 1) in uint block;
+
+layout (set = 0, binding = 0) readonly buffer BlockColors {
+    // RGBA8 of every block of the registry
+    uint block_colors[];
+};
 
 layout (location = 0) out vec4 out_color;
 
 layout (push_constant) uniform Transform {
     mat4 pvm;
+    // xyz is the direction light travels in
+    vec4 light_direction;
+    // rgb is scaled by intensity, a is the ambient term
+    vec4 light_color;
 };
 
+// in the order of chunk_mesher::FACES
+const vec3 NORMALS[6] = vec3[](
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(-1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0),
+    vec3(1.0, 0.0, 0.0)
+);
+
 void main() {
+    vec3 pos = vec3(data & 63u, (data >> 6) & 63u, (data >> 12) & 63u);
     vec4 new_pos = pvm * vec4(pos, 1.0);
     gl_Position = new_pos;
 
-    out_color = color;
-}
+    vec3 normal = NORMALS[(data >> 18) & 7u];
+    float diffuse = max(dot(normal, -light_direction.xyz), 0.0);
+    vec3 light = vec3(light_color.a) + light_color.rgb * diffuse;
+
+    float ao = float((data >> 21) & 3u) / 3.0;
+    vec4 color = unpackUnorm4x8(block_colors[block]);
+    out_color = vec4(color.rgb * light * mix(0.4, 1.0, ao), color.a);
+}
\ No newline at end of file